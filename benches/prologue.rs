@@ -0,0 +1,74 @@
+/*!
+Measures the length disassembly throughput on function prologues.
+
+The single byte corpus only takes the fast path, the wait corpus has single byte instructions which miss the fast path.
+Throughput is measured in instructions so the two can be compared directly, the mixed prologue corpora show the typical case.
+
+The window group compares decoding from 15 byte windows passed as slices against passing them as arrays, where the length is known at compile time.
+*/
+
+#[macro_use]
+extern crate criterion;
+extern crate lde;
+
+use criterion::measurement::WallTime;
+use criterion::{black_box, BenchmarkGroup, Criterion, Throughput};
+use lde::{Isa, X86, X64};
+
+// push rbp; push rbx; push rsi; push rdi; pop rdi; pop rsi; pop rbx; pop rbp; ret; nop; int3; int3
+const SINGLE: &[u8] = b"\x55\x53\x56\x57\x5F\x5E\x5B\x5D\xC3\x90\xCC\xCC";
+
+// wait; wait; ...
+// It may be the prefix of an x87 instruction so it goes through the full decoder
+const WAIT: &[u8] = b"\x9B\x9B\x9B\x9B\x9B\x9B\x9B\x9B\x9B\x9B\x9B\x9B";
+
+// push rbp; mov rbp, rsp; push rbx; sub rsp, 0x28; mov qword ptr [rbp-0x10], rcx; lea rax, [rip+0x100]
+// add rsp, 0x28; pop rbx; pop rbp; ret; int3
+const X64_PROLOGUE: &[u8] = b"\x55\x48\x89\xE5\x53\x48\x83\xEC\x28\x48\x89\x4D\xF0\x48\x8D\x05\x00\x01\x00\x00\x48\x83\xC4\x28\x5B\x5D\xC3\xCC";
+
+// push ebp; mov ebp, esp; push esi; sub esp, 0x10; mov esi, dword ptr [ebp+8]; mov eax, dword ptr [esi+eax*4+0x10]
+// pop esi; leave; ret 8; int3
+const X86_PROLOGUE: &[u8] = b"\x55\x8B\xEC\x56\x83\xEC\x10\x8B\x75\x08\x8B\x44\x86\x10\x5E\xC9\xC2\x08\x00\xCC";
+
+fn bench<X: Isa>(group: &mut BenchmarkGroup<WallTime>, name: &str, code: &[u8]) {
+	// Repeat the code to get a corpus of a reasonable size
+	let bytes: Vec<u8> = code.iter().cycle().take(code.len() * 256).cloned().collect();
+	group.throughput(Throughput::Elements(X::iter(&bytes, X::as_va(0)).count() as u64));
+	group.bench_function(name, |b| b.iter(|| X::iter(black_box(&bytes), X::as_va(0)).count()));
+}
+
+fn prologue(c: &mut Criterion) {
+	let mut group = c.benchmark_group("prologue");
+	bench::<X64>(&mut group, "single", SINGLE);
+	bench::<X64>(&mut group, "wait", WAIT);
+	bench::<X86>(&mut group, "x86", X86_PROLOGUE);
+	bench::<X64>(&mut group, "x64", X64_PROLOGUE);
+	group.finish();
+}
+
+// Decodes from an array so the length of the window is known after inlining
+#[inline(always)]
+fn ld_fixed<X: Isa>(window: &[u8; 15]) -> u32 {
+	X::ld(window)
+}
+
+fn window(c: &mut Criterion) {
+	// Over-read 15 bytes at every instruction of the prologue, padded with int3
+	let mut bytes = X64_PROLOGUE.to_vec();
+	bytes.extend_from_slice(&[0xCC; 15]);
+	let windows: Vec<[u8; 15]> = X64::iter(X64_PROLOGUE, 0).enumerate_offsets()
+		.map(|(offset, _)| {
+			let mut window = [0; 15];
+			window.copy_from_slice(&bytes[offset..offset + 15]);
+			window
+		})
+		.collect();
+	let mut group = c.benchmark_group("window");
+	group.throughput(Throughput::Elements(windows.len() as u64));
+	group.bench_function("slice", |b| b.iter(|| black_box(&windows).iter().map(|window| X64::ld(&window[..])).sum::<u32>()));
+	group.bench_function("fixed", |b| b.iter(|| black_box(&windows).iter().map(ld_fixed::<X64>).sum::<u32>()));
+	group.finish();
+}
+
+criterion_group!(benches, prologue, window);
+criterion_main!(benches);
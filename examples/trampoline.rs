@@ -0,0 +1,60 @@
+/*!
+Builds a trampoline for a jmp hook placed at the start of a function.
+
+The instructions overwritten by the hook are copied to the trampoline followed by a jmp back to the rest of the function.
+*/
+
+extern crate lde;
+
+use lde::{Isa, Iter, X86};
+
+// 1000: 56         push esi
+// 1001: 33f6       xor esi,esi
+// 1003: 57         push edi
+// 1004: bfa0104000 mov edi,0x4010a0
+// 1009: 85d2       test edx,edx
+// 100b: 7410       je loc_0000001d
+// 100d: 8bf2       mov esi,edx
+// 100f: 8bfa       mov edi,edx
+const INPUT_CODE: &[u8] = b"\x56\x33\xF6\x57\xBF\xA0\x10\x40\x00\x85\xD2\x74\x10\x8B\xF2\x8B\xFA";
+
+/// Counts the bytes of the whole instructions needed to cover at least `min` bytes.
+///
+/// Returns the number of bytes decoded so far if the input runs out first.
+fn count<X: Isa>(iter: Iter<X>, min: usize) -> Result<usize, usize> {
+	let mut count = 0;
+	for inst in iter {
+		count += inst.len();
+		if count >= min {
+			return Ok(count);
+		}
+	}
+	Err(count)
+}
+
+/// Copies the instructions overwritten by a jmp hook and appends a jmp back to the original code.
+///
+/// The trampoline is placed at the virtual address `dest`.
+/// Instructions referencing their own address (relative branches, RIP-relative operands) are copied as is and need relocating.
+fn trampoline<X: Isa>(iter: Iter<X>, dest: X::Va) -> Option<Vec<u8>> where X::Va: Into<u64> {
+	let (src, bytes) = (iter.va.into(), iter.bytes);
+	let len = count(iter, 5).ok()?;
+
+	let mut code = bytes[..len].to_vec();
+	let jmp = lde::jmp_rel32(dest.into() + len as u64, src + len as u64).ok()?;
+	code.extend_from_slice(&jmp);
+	Some(code)
+}
+
+fn main() {
+	// The answer is the first 4 instructions, or 9 bytes
+	assert_eq!(count(X86::iter(INPUT_CODE, 0x1000), 5), Ok(9));
+	assert_eq!(count(X86::iter(&INPUT_CODE[..3], 0x1000), 5), Err(3));
+
+	let code = trampoline(X86::iter(INPUT_CODE, 0x1000), 0x2000).unwrap();
+	println!("{}", X86::iter(&code, 0x2000));
+
+	// jmp 0x1009
+	assert_eq!(&code[..9], &INPUT_CODE[..9]);
+	assert_eq!(&code[9..], b"\xE9\xFB\xEF\xFF\xFF");
+}
@@ -0,0 +1,51 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate lde;
+
+use lde::{Isa, X16, X86, X64};
+
+fn check<X: Isa>(data: &[u8]) {
+	// The length disassembler must never claim more bytes than it was given
+	let len = X::ld(data) as usize;
+	assert!(len <= data.len());
+
+	// Iterating must consume the input without panicking
+	let mut total = 0;
+	for inst in X::iter(data, X::as_va(0)) {
+		assert!(!inst.bytes().is_empty());
+		assert_eq!(inst.bytes().len(), inst.prefix_bytes().len() + inst.op_bytes().len() + inst.arg_bytes().len());
+		total += inst.bytes().len();
+
+		// The accessors must stay within the instruction bytes
+		if let Some(offset) = inst.disp_offset() {
+			assert!(inst.modrm().is_some());
+			assert!(offset + inst.disp_size().unwrap() as usize <= inst.bytes().len());
+		}
+		if let Some(offset) = inst.imm_offset() {
+			assert!(offset + inst.imm_size().unwrap() as usize <= inst.bytes().len());
+		}
+		if inst.sib().is_some() {
+			assert!(inst.modrm().is_some());
+		}
+		let _ = inst.rip_target();
+		let _ = inst.branch_target();
+		let _ = inst.flow();
+		let _ = inst.accesses_memory();
+	}
+	assert!(total <= data.len());
+
+	// Classifying the first instruction agrees with its length
+	if let Some(info) = X::probe(data, X::as_va(0)) {
+		assert_eq!(info.len.total_len as usize, len);
+	}
+	if let Ok(info) = X::hook_prologue(data, X::as_va(0), 5) {
+		assert!(info.len >= 5 && info.len <= data.len());
+	}
+}
+
+fuzz_target!(|data: &[u8]| {
+	check::<X16>(data);
+	check::<X86>(data);
+	check::<X64>(data);
+});
@@ -2,11 +2,12 @@
 Defines the x86 instruction struct.
  */
 
-use core::{fmt};
-use {Isa, fmt_bytes};
+use core::{fmt, ops};
+use {Int, Isa, OutOfRange, Va, fmt_bytes, read, try_read, write, x86};
 
 /// Instruction length in bytes.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InstLen {
 	/// Total length of the instruction.
 	pub total_len: u8,
@@ -19,6 +20,233 @@ pub struct InstLen {
 }
 impl InstLen {
 	pub const EMPTY: InstLen = InstLen { total_len: 0, op_len: 0, arg_len: 0, prefix_len: 0 };
+	/// Returns whether the prefix, operation code and argument bytes add up to the total length.
+	///
+	/// ```
+	/// use lde::{Isa, InstLen, X64};
+	/// assert!(X64::inst_len(b"\x48\x83\xEC*").is_consistent());
+	/// assert!(!InstLen { total_len: 4, op_len: 1, arg_len: 1, prefix_len: 1 }.is_consistent());
+	/// ```
+	pub fn is_consistent(&self) -> bool {
+		self.prefix_len as u32 + self.op_len as u32 + self.arg_len as u32 == self.total_len as u32
+	}
+	/// Gets the offset of the operation code.
+	pub fn op_start(&self) -> usize {
+		self.prefix_len as usize
+	}
+	/// Gets the offset of the arguments.
+	pub fn arg_start(&self) -> usize {
+		self.prefix_len as usize + self.op_len as usize
+	}
+	/// Gets the byte ranges of the prefixes, operation code and arguments.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // sub rsp, 0x2a
+	/// let (prefix, op, arg) = X64::inst_len(b"\x48\x83\xEC*").ranges();
+	/// assert_eq!((prefix, op, arg), (0..1, 1..2, 2..4));
+	/// ```
+	pub fn ranges(&self) -> (ops::Range<usize>, ops::Range<usize>, ops::Range<usize>) {
+		(0..self.op_start(), self.op_start()..self.arg_start(), self.arg_start()..self.total_len as usize)
+	}
+}
+
+/// Length disassembling error.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum LenError {
+	/// The byte slice ends before the instruction does.
+	///
+	/// When the byte slice ends before the instruction length can be determined, `need` is a lower bound.
+	Truncated {
+		/// Number of bytes available.
+		have: usize,
+		/// Number of bytes needed to complete the instruction.
+		need: usize,
+	},
+	/// The bytes do not encode a valid instruction.
+	Invalid,
+}
+
+/// Default operand or address size.
+///
+/// Used by [`Isa::inst_len_sized`](trait.Isa.html#method.inst_len_sized) and [`Isa::ADDR_SIZE`](trait.Isa.html#associatedconstant.ADDR_SIZE).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Size {
+	/// 16-bit.
+	Bits16,
+	/// 32-bit.
+	Bits32,
+	/// 64-bit.
+	Bits64,
+}
+
+/// Control flow classification.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Flow {
+	/// Execution continues with the next instruction.
+	Next,
+	/// Unconditional jump.
+	Jump,
+	/// Conditional jump.
+	Branch,
+	/// Call, including `syscall` and `sysenter`.
+	Call,
+	/// Return, including `iret`, `sysret` and `sysexit`.
+	Return,
+	/// Software interrupt.
+	Interrupt,
+}
+
+/// Segment register.
+///
+/// Returned by [`Inst::segment`](struct.Inst.html#method.segment).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Segment {
+	/// Override prefix 26.
+	ES,
+	/// Override prefix 2E, also the branch not taken hint.
+	CS,
+	/// Override prefix 36.
+	SS,
+	/// Override prefix 3E, also the branch taken hint.
+	DS,
+	/// Override prefix 64.
+	FS,
+	/// Override prefix 65.
+	GS,
+}
+
+/// Opcode map of an instruction.
+///
+/// Returned by [`Inst::opcode_map`](struct.Inst.html#method.opcode_map).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum OpcodeMap {
+	/// One-byte opcodes.
+	Primary,
+	/// Two-byte opcodes escaped by `0F`, including 3DNow!.
+	Secondary,
+	/// Three-byte opcodes escaped by `0F 38`.
+	ThreeByte38,
+	/// Three-byte opcodes escaped by `0F 3A`.
+	ThreeByte3A,
+	/// VEX encoded opcodes.
+	Vex,
+	/// EVEX encoded opcodes.
+	Evex,
+	/// XOP encoded opcodes.
+	Xop,
+}
+
+/// Instruction summary.
+///
+/// Instances are created by the [`Isa::probe`](trait.Isa.html#method.probe) method.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct InstInfo {
+	/// Instruction length in bytes.
+	pub len: InstLen,
+	/// Control flow classification.
+	pub flow: Flow,
+	/// Whether the instruction has an explicit memory operand.
+	pub mem: bool,
+}
+
+/// Instructions overwritten by a hook.
+///
+/// Returned by [`Isa::hook_prologue`](trait.Isa.html#method.hook_prologue).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PrologueInfo {
+	/// Number of bytes to preserve, ends on an instruction boundary.
+	pub len: usize,
+	/// Number of instructions to preserve.
+	pub count: usize,
+	/// Whether any of the preserved instructions is RIP-relative or transfers control and cannot simply be copied.
+	pub needs_relocation: bool,
+}
+
+/// The byte slice ends before enough whole instructions could be length disassembled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct NotEnoughBytes;
+
+// Returns the offset of the VEX, EVEX or XOP prefix in the prefix bytes, or their length for legacy encoded instructions.
+// The legacy prefixes never take the values of the VEX, EVEX and XOP escapes.
+fn vex_start(prefix_bytes: &[u8]) -> usize {
+	prefix_bytes.iter().position(|&byte| byte == 0xC4 || byte == 0xC5 || byte == 0x62 || byte == 0x8F).unwrap_or(prefix_bytes.len())
+}
+
+// Returns the opcode map selected by the VEX, EVEX or XOP prefix, zero for legacy encoded instructions.
+pub(crate) fn vex_map(prefix_bytes: &[u8]) -> u8 {
+	let i = vex_start(prefix_bytes);
+	match prefix_bytes.get(i) {
+		None => 0,
+		Some(0xC5) => 1,
+		Some(0xC4) | Some(0x8F) => prefix_bytes[i + 1] & 0x1F,
+		Some(_) => prefix_bytes[i + 1] & 0x07,
+	}
+}
+
+/// Legacy and REX prefixes of an instruction.
+///
+/// Instances are created by the [`Inst::prefixes`](struct.Inst.html#method.prefixes) method.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Prefixes<'a> {
+	bytes: &'a [u8],
+}
+impl<'a> Prefixes<'a> {
+	/// Gets the prefix bytes, excluding the VEX and EVEX prefixes.
+	pub fn bytes(&self) -> &'a [u8] {
+		self.bytes
+	}
+	/// Returns whether the `lock` prefix (F0) is present.
+	pub fn lock(&self) -> bool {
+		self.bytes.contains(&0xF0)
+	}
+	/// Returns whether the `rep` prefix (F3) is present.
+	pub fn rep(&self) -> bool {
+		self.bytes.contains(&0xF3)
+	}
+	/// Returns whether the `repne` prefix (F2) is present.
+	pub fn repne(&self) -> bool {
+		self.bytes.contains(&0xF2)
+	}
+	/// Returns whether the operand size override prefix (66) is present.
+	pub fn operand_size(&self) -> bool {
+		self.bytes.contains(&0x66)
+	}
+	/// Returns whether the address size override prefix (67) is present.
+	pub fn address_size(&self) -> bool {
+		self.bytes.contains(&0x67)
+	}
+	/// Gets the segment override prefix (26, 2E, 36, 3E, 64 or 65).
+	///
+	/// When multiple segment override prefixes are present the last one takes effect.
+	pub fn segment(&self) -> Option<u8> {
+		self.bytes.iter().rev().cloned().find(|&byte| matches!(byte, 0x26 | 0x2E | 0x36 | 0x3E | 0x64 | 0x65))
+	}
+	/// Gets the REX prefix (40..4F), only available on `x86_64`.
+	///
+	/// The REX prefix is ignored unless it directly precedes the opcode.
+	pub fn rex(&self) -> Option<u8> {
+		match self.bytes.last() {
+			Some(&byte) if byte & 0xF0 == 0x40 => Some(byte),
+			_ => None,
+		}
+	}
+	/// Returns whether the REX.W bit is set, selecting 64-bit operands.
+	pub fn rex_w(&self) -> bool {
+		self.rex().map_or(0, |rex| rex & 8) != 0
+	}
+	/// Returns whether the REX.R bit is set, extending the ModRM reg field.
+	pub fn rex_r(&self) -> bool {
+		self.rex().map_or(0, |rex| rex & 4) != 0
+	}
+	/// Returns whether the REX.X bit is set, extending the SIB index field.
+	pub fn rex_x(&self) -> bool {
+		self.rex().map_or(0, |rex| rex & 2) != 0
+	}
+	/// Returns whether the REX.B bit is set, extending the ModRM rm, SIB base or opcode reg field.
+	pub fn rex_b(&self) -> bool {
+		self.rex().map_or(0, |rex| rex & 1) != 0
+	}
 }
 
 /// Instruction.
@@ -33,6 +261,7 @@ impl<'a, X: Isa> Clone for Inst<'a, X> {
 }
 impl<'a, X: Isa> Inst<'a, X> {
 	pub(crate) fn new(bytes: &'a [u8], va: X::Va, len: InstLen) -> Inst<'a, X> {
+		debug_assert!(len.is_consistent(), "{:?}", len);
 		Inst { bytes, va, len }
 	}
 	/// Gets the instruction bytes.
@@ -41,31 +270,541 @@ impl<'a, X: Isa> Inst<'a, X> {
 	}
 	/// Gets the bytes part of the instruction prefixes (if any).
 	pub fn prefix_bytes(&self) -> &'a [u8] {
-		let end = self.len.prefix_len as usize;
-		&self.bytes[..end]
+		&self.bytes[..self.len.op_start()]
+	}
+	/// Gets the legacy and REX prefixes.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // lock add qword ptr [rax], rax
+	/// let inst = X64::iter(b"\xF0\x48\x01\x00", 0x1000).next().unwrap();
+	/// let prefixes = inst.prefixes();
+	/// assert!(prefixes.lock() && prefixes.rex_w());
+	/// assert!(!prefixes.rep() && !prefixes.operand_size());
+	/// assert_eq!(prefixes.segment(), None);
+	/// ```
+	pub fn prefixes(&self) -> Prefixes<'a> {
+		Prefixes { bytes: self.legacy_prefix_bytes() }
+	}
+	/// Gets the REX prefix directly preceding the opcode, if any.
+	///
+	/// Always `None` outside of 64-bit mode, where `40..4F` are opcodes.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // add rax, rbx
+	/// assert_eq!(X64::iter(b"\x48\x01\xD8", 0x1000).next().unwrap().rex(), Some(0x48));
+	/// // add eax, ebx
+	/// assert_eq!(X64::iter(b"\x01\xD8", 0x1000).next().unwrap().rex(), None);
+	/// ```
+	pub fn rex(&self) -> Option<u8> {
+		self.prefixes().rex()
+	}
+	/// Gets the segment override, if any.
+	///
+	/// In 64-bit mode the ES, CS, SS and DS overrides are reported but have no effect on addressing.
+	///
+	/// ```
+	/// use lde::{Isa, Segment, X86};
+	/// // mov eax, dword ptr fs:[0]
+	/// let inst = X86::iter(b"\x64\xA1\x00\x00\x00\x00", 0x1000).next().unwrap();
+	/// assert_eq!(inst.segment(), Some(Segment::FS));
+	/// ```
+	pub fn segment(&self) -> Option<Segment> {
+		match self.prefixes().segment()? {
+			0x26 => Some(Segment::ES),
+			0x2E => Some(Segment::CS),
+			0x36 => Some(Segment::SS),
+			0x3E => Some(Segment::DS),
+			0x64 => Some(Segment::FS),
+			_ => Some(Segment::GS),
+		}
 	}
 	/// Gets the bytes part of the instruction opcode.
 	pub fn op_bytes(&self) -> &'a [u8] {
-		let start = self.len.prefix_len as usize;
-		let end = start + self.len.op_len as usize;
-		&self.bytes[start..end]
+		&self.bytes[self.len.op_start()..self.len.arg_start()]
+	}
+	/// Gets the opcode map of the instruction.
+	///
+	/// ```
+	/// use lde::{Isa, OpcodeMap, X64};
+	/// // movzx eax, cl
+	/// let inst = X64::iter(b"\x0F\xB6\xC1", 0x1000).next().unwrap();
+	/// assert_eq!(inst.opcode_map(), OpcodeMap::Secondary);
+	/// ```
+	pub fn opcode_map(&self) -> OpcodeMap {
+		let prefix_bytes = self.prefix_bytes();
+		match prefix_bytes.get(vex_start(prefix_bytes)) {
+			Some(0x62) => OpcodeMap::Evex,
+			Some(0x8F) => OpcodeMap::Xop,
+			Some(_) => OpcodeMap::Vex,
+			None => match self.op_bytes() {
+				[0x0F, 0x38, ..] => OpcodeMap::ThreeByte38,
+				[0x0F, 0x3A, ..] => OpcodeMap::ThreeByte3A,
+				[0x0F, ..] => OpcodeMap::Secondary,
+				_ => OpcodeMap::Primary,
+			},
+		}
 	}
 	/// Gets the bytes part of the instruction arguments.
 	pub fn arg_bytes(&self) -> &'a [u8] {
-		let end = self.len.total_len as usize;
-		let start = end - self.len.arg_len as usize;
-		&self.bytes[start..end]
+		&self.bytes[self.len.arg_start()..self.len.total_len as usize]
+	}
+	/// Gets the prefix, opcode and argument bytes in one call.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // sub rsp, 0x2a
+	/// let inst = X64::iter(b"\x48\x83\xEC*", 0x1000).next().unwrap();
+	/// assert_eq!(inst.parts(), (&b"\x48"[..], &b"\x83"[..], &b"\xEC*"[..]));
+	/// ```
+	pub fn parts(&self) -> (&'a [u8], &'a [u8], &'a [u8]) {
+		(self.prefix_bytes(), self.op_bytes(), self.arg_bytes())
 	}
 	/// Gets the virtual address
 	pub fn va(&self) -> X::Va {
 		self.va
 	}
+	/// Gets the length of the instruction in bytes.
+	///
+	/// This is the total length of the prefix, opcode and argument bytes.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // sub rsp, 0x2a
+	/// let inst = X64::iter(b"\x48\x83\xEC*", 0x1000).next().unwrap();
+	/// assert_eq!(inst.len(), inst.bytes().len());
+	/// assert!(!inst.is_empty());
+	/// ```
+	pub fn len(&self) -> usize {
+		self.len.total_len as usize
+	}
+	#[doc(hidden)]
+	pub fn total_len(&self) -> usize {
+		self.len()
+	}
+	/// Returns `true` if the instruction has no bytes, which is never the case for decoded instructions.
+	pub fn is_empty(&self) -> bool {
+		self.len.total_len == 0
+	}
+	/// Gets the length of the instruction arguments in bytes.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // sub rsp, 0x2a
+	/// let inst = X64::iter(b"\x48\x83\xEC*", 0x1000).next().unwrap();
+	/// assert_eq!(inst.len(), 4);
+	/// assert_eq!(inst.arg_len(), 2);
+	/// ```
+	pub fn arg_len(&self) -> usize {
+		self.len.arg_len as usize
+	}
+	// Gets the legacy prefix bytes, excluding the VEX or EVEX prefix.
+	fn legacy_prefix_bytes(&self) -> &'a [u8] {
+		let prefix_bytes = self.prefix_bytes();
+		&prefix_bytes[..vex_start(prefix_bytes)]
+	}
+	// Gets the bytes part of the opcode of legacy encoded instructions.
+	// Empty for the VEX and EVEX encodings, their opcode maps reuse the legacy opcode values.
+	fn legacy_op_bytes(&self) -> &'a [u8] {
+		if vex_map(self.prefix_bytes()) != 0 { &[] } else { self.op_bytes() }
+	}
+	/// Gets the ModRM byte, if the instruction has one.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// // mov eax, dword ptr [ebx]
+	/// assert_eq!(X86::iter(b"\x8B\x03", 0x1000).next().unwrap().modrm(), Some(0x03));
+	/// // nop
+	/// assert_eq!(X86::iter(b"\x90", 0x1000).next().unwrap().modrm(), None);
+	/// ```
+	pub fn modrm(&self) -> Option<u8> {
+		if x86::has_modrm(self.bytes, &self.len) { Some(self.arg_bytes()[0]) } else { None }
+	}
+	// Gets the ModRM byte if it encodes a memory operand.
+	// The register forms, including those which ignore the ModRM mod field, have no SIB byte or displacement.
+	fn mem_modrm(&self) -> Option<u8> {
+		let modrm = self.modrm()?;
+		if modrm >= 0xC0 || x86::reg_form(self.bytes, &self.len) { None } else { Some(modrm) }
+	}
+	/// Gets the SIB byte, if the instruction has one.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// // mov eax, dword ptr [eax+ecx*4]
+	/// assert_eq!(X86::iter(b"\x8B\x04\x88", 0x1000).next().unwrap().sib(), Some(0x88));
+	/// // mov eax, dword ptr [ebx]
+	/// assert_eq!(X86::iter(b"\x8B\x03", 0x1000).next().unwrap().sib(), None);
+	/// ```
+	pub fn sib(&self) -> Option<u8> {
+		let modrm = self.mem_modrm()?;
+		if self.addr_size() == 16 || modrm & 0x07 != 0x04 {
+			return None;
+		}
+		Some(self.arg_bytes()[1])
+	}
+	/// Gets the offset of the memory operand displacement in the instruction bytes, if the instruction has one.
+	///
+	/// Only ModRM memory operands are considered, the absolute offset of the `mov` with memory offset (A0..A3) is not a displacement.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// // mov eax, dword ptr [ebp+0x10]
+	/// let inst = X86::iter(b"\x8B\x45\x10", 0x1000).next().unwrap();
+	/// assert_eq!(inst.disp_offset(), Some(2));
+	/// assert_eq!(inst.disp_size(), Some(1));
+	/// assert_eq!(inst.read::<i8>(inst.disp_offset().unwrap()), 0x10);
+	/// ```
+	pub fn disp_offset(&self) -> Option<usize> {
+		self.disp().map(|(offset, _)| offset)
+	}
+	/// Gets the size of the memory operand displacement in bytes, if the instruction has one.
+	///
+	/// The size is 1 or 4, or 2 for the 16-bit addressing forms.
+	pub fn disp_size(&self) -> Option<u8> {
+		self.disp().map(|(_, size)| size)
+	}
+	// Offset and size of the memory operand displacement.
+	fn disp(&self) -> Option<(usize, u8)> {
+		let modrm = self.mem_modrm()?;
+		let offset = (self.len.total_len - self.len.arg_len) as usize + 1;
+		let (mode, rm) = (modrm >> 6, modrm & 0x07);
+		if self.addr_size() == 16 {
+			return match (mode, rm) {
+				(0, 6) | (2, _) => Some((offset, 2)),
+				(1, _) => Some((offset, 1)),
+				_ => None,
+			};
+		}
+		// The SIB byte comes before the displacement
+		let (offset, base) = if rm == 4 { (offset + 1, self.arg_bytes()[1] & 0x07) } else { (offset, rm) };
+		match (mode, base) {
+			(0, 5) | (2, _) => Some((offset, 4)),
+			(1, _) => Some((offset, 1)),
+			_ => None,
+		}
+	}
+	/// Gets the offset of the immediate operand in the instruction bytes, if the instruction has one.
+	///
+	/// The immediate bytes are the argument bytes following the ModRM byte, SIB byte and displacement.
+	/// These include the relative offset of branches and both immediates of `enter`.
+	/// The absolute offset of the `mov` with memory offset (A0..A3) is not an immediate.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// // add dword ptr [ebp-4], 0x1234
+	/// let inst = X86::iter(b"\x81\x45\xFC\x34\x12\x00\x00", 0x1000).next().unwrap();
+	/// assert_eq!(inst.imm_offset(), Some(3));
+	/// assert_eq!(inst.imm_size(), Some(4));
+	/// assert_eq!(inst.read::<u32>(inst.imm_offset().unwrap()), 0x1234);
+	/// ```
+	pub fn imm_offset(&self) -> Option<usize> {
+		self.imm().map(|(offset, _)| offset)
+	}
+	/// Gets the size of the immediate operand in bytes, if the instruction has one.
+	pub fn imm_size(&self) -> Option<u8> {
+		self.imm().map(|(_, size)| size)
+	}
+	// Offset and size of the immediate operand.
+	fn imm(&self) -> Option<(usize, u8)> {
+		if let &[0xA0..=0xA3] = self.legacy_op_bytes() {
+			return None;
+		}
+		let start = (self.len.total_len - self.len.arg_len) as usize;
+		let offset = match (self.modrm(), self.disp()) {
+			(_, Some((offset, size))) => offset + size as usize,
+			(Some(_), None) => start + 1 + self.sib().is_some() as usize,
+			(None, None) => start,
+		};
+		let size = self.len.total_len as usize - offset;
+		if size == 0 { None } else { Some((offset, size as u8)) }
+	}
+	/// Compares the instruction bytes against a template.
+	///
+	/// Template bytes equal to `wildcard` match any byte, the template must be as long as the instruction.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// // mov edi, 0x4010a0
+	/// let inst = X86::iter(b"\xBF\xA0\x10\x40\x00", 0x1000).next().unwrap();
+	/// assert!(inst.matches(b"\xBF????", b'?'));
+	/// assert!(!inst.matches(b"\xBE????", b'?'));
+	/// assert!(!inst.matches(b"\xBF??", b'?'));
+	/// ```
+	pub fn matches(&self, template: &[u8], wildcard: u8) -> bool {
+		self.bytes.len() == template.len() && self.bytes.iter().zip(template).all(|(&byte, &pat)| pat == wildcard || pat == byte)
+	}
+	/// Reads an immediate or displacement value at the given offset in the instruction bytes.
+	///
+	/// See [`read`](fn.read.html) for more information.
+	pub fn read<T: Int>(&self, offset: usize) -> T {
+		read(self.bytes, offset)
+	}
+	/// Reads an immediate or displacement value at the given offset in the instruction bytes.
+	///
+	/// See [`try_read`](fn.try_read.html) for more information.
+	pub fn try_read<T: Int>(&self, offset: usize) -> Option<T> {
+		try_read(self.bytes, offset)
+	}
+	// Effective address size, taking the address size override prefix into account.
+	fn addr_size(&self) -> u32 {
+		match (X::ADDR_SIZE, self.prefixes().address_size()) {
+			(Size::Bits64, false) => 64,
+			(Size::Bits32, false) | (Size::Bits64, true) | (Size::Bits16, true) => 32,
+			(Size::Bits16, false) | (Size::Bits32, true) => 16,
+		}
+	}
+	// Opcode extension in the reg field of the ModRM byte directly following the opcode.
+	fn ext_op(&self) -> u8 {
+		(self.arg_bytes()[0] >> 3) & 7
+	}
+	/// Gets the operation selected by the ModRM reg field of group opcodes.
+	///
+	/// The group opcodes are the immediate arithmetic (80..83), `pop` (8F), shifts (C0, C1, D0..D3), `mov` (C6, C7), the unary groups (F6, F7, FE, FF)
+	/// and the two-byte groups (0F 00, 0F 01, 0F 18, 0F 71..73, 0F AE, 0F BA, 0F C7).
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // call rax
+	/// assert_eq!(X64::iter(b"\xFF\xD0", 0x1000).next().unwrap().group_index(), Some(2));
+	/// // inc eax
+	/// assert_eq!(X64::iter(b"\xFF\xC0", 0x1000).next().unwrap().group_index(), Some(0));
+	/// // mov eax, ecx
+	/// assert_eq!(X64::iter(b"\x8B\xC1", 0x1000).next().unwrap().group_index(), None);
+	/// ```
+	pub fn group_index(&self) -> Option<u8> {
+		match self.legacy_op_bytes() {
+			&[0x80..=0x83] | &[0x8F] | &[0xC0] | &[0xC1] | &[0xC6] | &[0xC7] | &[0xD0..=0xD3] | &[0xF6] | &[0xF7] | &[0xFE] | &[0xFF] => Some(self.ext_op()),
+			&[0x0F, 0x00] | &[0x0F, 0x01] | &[0x0F, 0x18] | &[0x0F, 0x71..=0x73] | &[0x0F, 0xAE] | &[0x0F, 0xBA] | &[0x0F, 0xC7] => Some(self.ext_op()),
+			_ => None,
+		}
+	}
+	/// Returns the address referenced by a RIP-relative memory operand.
+	///
+	/// RIP-relative addressing is only available on `x86_64` and is relative to the end of the instruction.
+	/// With the address size override prefix the operand is EIP-relative and the address is truncated to 32 bits.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // lea rax, [rip+0x10]
+	/// let inst = X64::iter(b"\x48\x8D\x05\x10\x00\x00\x00", 0x1000).next().unwrap();
+	/// assert_eq!(inst.rip_target(), Some(0x1017));
+	/// ```
+	pub fn rip_target(&self) -> Option<X::Va> {
+		if X::ADDR_SIZE != Size::Bits64 || self.mem_modrm()? & 0xC7 != 0x05 {
+			return None;
+		}
+		let disp: i32 = read(self.arg_bytes(), 1);
+		let target = self.va.offset(self.len.total_len as i64 + disp as i64);
+		if self.addr_size() == 32 {
+			let high = target.delta(X::as_va(0)) & !0xFFFF_FFFF;
+			return Some(target.offset(high.wrapping_neg()));
+		}
+		Some(target)
+	}
+	/// Returns the target address of a relative branch.
+	///
+	/// These are the relative `call`, `jmp`, `jcc`, `loop` and `jcxz`, the target is relative to the end of the instruction.
+	/// Returns `None` for indirect branches and other instructions.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// // jmp $+0x12
+	/// let inst = X86::iter(b"\xEB\x10", 0x1000).next().unwrap();
+	/// assert_eq!(inst.branch_target(), Some(0x1012));
+	/// ```
+	pub fn branch_target(&self) -> Option<X::Va> {
+		match self.legacy_op_bytes() {
+			&[0x70..=0x7F] | &[0xE0..=0xE3] | &[0xE8] | &[0xE9] | &[0xEB] | &[0x0F, 0x80..=0x8F] => (),
+			_ => return None,
+		}
+		let (offset, size) = self.imm()?;
+		let rel = match size {
+			1 => read::<i8>(self.bytes, offset) as i64,
+			2 => read::<i16>(self.bytes, offset) as i64,
+			_ => read::<i32>(self.bytes, offset) as i64,
+		};
+		Some(self.va.offset(self.len.total_len as i64 + rel))
+	}
+	/// Copies the instruction to `out` as if it were moved to the virtual address `va`.
+	///
+	/// The displacement of a RIP-relative memory operand is adjusted to keep referencing the same address.
+	/// Other instructions, including relative branches, are copied unchanged.
+	///
+	/// Returns `OutOfRange` if the adjusted displacement does not fit, `out` is left untouched.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // lea rax, [rip+0x10]
+	/// let inst = X64::iter(b"\x48\x8D\x05\x10\x00\x00\x00", 0x1000).next().unwrap();
+	/// let mut out = [0; 7];
+	/// assert_eq!(inst.relocate(0x1100, &mut out), Ok(()));
+	/// // lea rax, [rip-0xf0]
+	/// assert_eq!(out, *b"\x48\x8D\x05\x10\xFF\xFF\xFF");
+	/// ```
+	///
+	/// # Panics
+	///
+	/// Panics if `out` is shorter than the instruction.
+	pub fn relocate(&self, va: X::Va, out: &mut [u8]) -> Result<(), OutOfRange> {
+		let out = &mut out[..self.bytes.len()];
+		if self.rip_target().is_none() {
+			out.copy_from_slice(self.bytes);
+			return Ok(());
+		}
+		let offset = (self.len.total_len - self.len.arg_len) as usize + 1;
+		let disp = read::<i32>(self.bytes, offset) as i64 + self.va.delta(va);
+		if disp < i32::MIN as i64 || disp > i32::MAX as i64 {
+			return Err(OutOfRange);
+		}
+		out.copy_from_slice(self.bytes);
+		write(out, offset, disp as i32);
+		Ok(())
+	}
+	/// Returns the control flow classification.
+	pub fn flow(&self) -> Flow {
+		match self.legacy_op_bytes() {
+			// jcc rel8, loop, jcxz, jcc rel32
+			&[0x70..=0x7F] | &[0xE0..=0xE3] | &[0x0F, 0x80..=0x8F] => Flow::Branch,
+			// jmp rel32, jmp ptr16:32, jmp rel8
+			&[0xE9] | &[0xEA] | &[0xEB] => Flow::Jump,
+			// call rel32, call ptr16:32, syscall, sysenter
+			&[0xE8] | &[0x9A] | &[0x0F, 0x05] | &[0x0F, 0x34] => Flow::Call,
+			// ret imm16, ret, retf imm16, retf, iret, sysret, sysexit
+			&[0xC2] | &[0xC3] | &[0xCA] | &[0xCB] | &[0xCF] | &[0x0F, 0x07] | &[0x0F, 0x35] => Flow::Return,
+			// int3, int imm8, into, int1
+			&[0xCC] | &[0xCD] | &[0xCE] | &[0xF1] => Flow::Interrupt,
+			// call r/m, call m16:32, jmp r/m, jmp m16:32
+			&[0xFF] => match self.ext_op() {
+				2 | 3 => Flow::Call,
+				4 | 5 => Flow::Jump,
+				_ => Flow::Next,
+			},
+			_ => Flow::Next,
+		}
+	}
+	/// Returns whether the instruction is a call, see [`Flow::Call`](enum.Flow.html#variant.Call).
+	pub fn is_call(&self) -> bool {
+		self.flow() == Flow::Call
+	}
+	/// Returns whether the instruction is an unconditional jump, see [`Flow::Jump`](enum.Flow.html#variant.Jump).
+	pub fn is_jmp(&self) -> bool {
+		self.flow() == Flow::Jump
+	}
+	/// Returns whether the instruction is a conditional jump, see [`Flow::Branch`](enum.Flow.html#variant.Branch).
+	pub fn is_conditional_jmp(&self) -> bool {
+		self.flow() == Flow::Branch
+	}
+	/// Returns whether the instruction is a return, see [`Flow::Return`](enum.Flow.html#variant.Return).
+	pub fn is_ret(&self) -> bool {
+		self.flow() == Flow::Return
+	}
+	/// Returns whether the instruction ends a basic block.
+	///
+	/// Terminators are unconditional and conditional jumps, returns (including `sysret` and `sysexit`) and software interrupts.
+	/// Calls (including `syscall` and `sysenter`) are not considered terminators, see [`is_terminator_or_call`](#method.is_terminator_or_call).
+	pub fn is_terminator(&self) -> bool {
+		match self.flow() {
+			Flow::Next | Flow::Call => false,
+			Flow::Jump | Flow::Branch | Flow::Return | Flow::Interrupt => true,
+		}
+	}
+	/// Returns whether the instruction ends a basic block, counting calls as terminators.
+	pub fn is_terminator_or_call(&self) -> bool {
+		self.flow() != Flow::Next
+	}
+	/// Returns whether the instruction may raise an exception.
+	///
+	/// This is a best-effort heuristic flagging the common cases:
+	///
+	/// * Instructions with an explicit memory operand, see [`accesses_memory`](#method.accesses_memory).
+	/// * String instructions (`movs`, `cmps`, `stos`, `lods`, `scas`, `ins`, `outs`).
+	/// * Division (`div`, `idiv`) and `bound`.
+	/// * Software interrupts and `ud2`.
+	/// * Privileged and I/O instructions (`hlt`, `cli`, `sti`, `in`, `out`, system and control register instructions).
+	///
+	/// Implicit stack accesses by `push`, `pop`, `call` and `ret` are not considered.
+	pub fn may_fault(&self) -> bool {
+		if self.accesses_memory() {
+			return true;
+		}
+		match self.legacy_op_bytes() {
+			// ins, outs, movs, cmps, stos, lods, scas
+			&[0x6C..=0x6F] | &[0xA4..=0xA7] | &[0xAA..=0xAF] => true,
+			// bound, int3, int imm8, into, int1
+			&[0x62] | &[0xCC] | &[0xCD] | &[0xCE] | &[0xF1] => true,
+			// in, out, hlt, cli, sti
+			&[0xE4..=0xE7] | &[0xEC..=0xEF] | &[0xF4] | &[0xFA] | &[0xFB] => true,
+			// div, idiv
+			&[0xF6] | &[0xF7] => self.ext_op() >= 6,
+			// system groups, clts, sysret, invd, wbinvd, ud2
+			&[0x0F, 0x00..=0x01] | &[0x0F, 0x06..=0x09] | &[0x0F, 0x0B] => true,
+			// mov to/from control and debug registers, wrmsr, rdmsr, sysexit
+			&[0x0F, 0x20..=0x23] | &[0x0F, 0x30] | &[0x0F, 0x32] | &[0x0F, 0x35] => true,
+			_ => false,
+		}
+	}
+	/// Returns whether the instruction is an atomic memory access.
+	///
+	/// These are the instructions with a `lock` prefix and the `xchg` with a memory operand, which is implicitly locked.
+	pub fn is_atomic(&self) -> bool {
+		if self.prefixes().lock() {
+			return true;
+		}
+		match self.legacy_op_bytes() {
+			&[0x86] | &[0x87] => self.arg_bytes()[0] < 0xC0,
+			_ => false,
+		}
+	}
+	/// Returns whether the immediate operand is sign extended to the operand size.
+	///
+	/// These are the `imm8` forms of `push` (6A), `imul` (6B) and the group 1 arithmetic (83),
+	/// and the `imm32` forms which sign extend when operating on 64-bit operands:
+	/// the arithmetic with `eax` (05, 0D, 15, 1D, 25, 2D, 35, 3D), `push` (68), `imul` (69), group 1 (81), `test` (A9, F7) and `mov` (C7).
+	///
+	/// The `mov` with register and immediate (B0..BF) and all the byte sized arithmetic never sign extend.
+	pub fn imm_is_sign_extended(&self) -> bool {
+		match self.legacy_op_bytes() {
+			&[0x6A] | &[0x6B] | &[0x83] => true,
+			&[0x05] | &[0x0D] | &[0x15] | &[0x1D] | &[0x25] | &[0x2D] | &[0x35] | &[0x3D] => true,
+			&[0x68] | &[0x69] | &[0x81] | &[0xA9] | &[0xC7] => true,
+			// test r/m, imm32
+			&[0xF7] => self.ext_op() < 2,
+			_ => false,
+		}
+	}
+	/// Returns whether the instruction has an explicit memory operand.
+	///
+	/// These are the ModRM memory forms and the `mov` with absolute memory offset.
+	/// The `lea` and `nop` instructions do not access memory despite their ModRM memory form.
+	pub fn accesses_memory(&self) -> bool {
+		match self.legacy_op_bytes() {
+			// mov moffs
+			&[0xA0..=0xA3] => return true,
+			// lea, nop
+			&[0x8D] | &[0x0F, 0x1F] => return false,
+			_ => (),
+		}
+		self.mem_modrm().is_some()
+	}
 }
 impl<'a, X: Isa> fmt::Debug for Inst<'a, X> {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		fmt::LowerHex::fmt(self, f)
 	}
 }
+/// Display formatter.
+///
+/// Lowercase hex bytes, the same as the iterator formatters.
+/// Alternate flag to put spaces between the bytes.
+///
+/// ```
+/// use lde::{Isa, X64};
+/// let inst = X64::iter(b"\x48\x83\xEC*", 0x1000).next().unwrap();
+/// assert_eq!(format!("{}", inst), "4883ec2a");
+/// assert_eq!(format!("{:#}", inst), "48 83 ec 2a");
+/// assert_eq!(format!("{:X}", inst), "4883EC2A");
+/// ```
 impl<'a, X: Isa> fmt::Display for Inst<'a, X> {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		fmt::LowerHex::fmt(self, f)
@@ -81,3 +820,391 @@ impl<'a, X: Isa> fmt::LowerHex for Inst<'a, X> {
 		fmt_bytes(self.bytes, b'a', f)
 	}
 }
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+use {X16, X86, X64};
+
+#[cfg(test)]
+fn first<X: Isa>(bytes: &[u8]) -> Inst<'_, X> {
+	X::iter(bytes, X::as_va(0)).next().unwrap()
+}
+
+#[test]
+fn terminators() {
+	// jmp rel8, jne rel32, ret, int3
+	assert!(first::<X86>(b"\xEB\x10").is_terminator());
+	assert!(first::<X64>(b"\x0F\x85****").is_terminator());
+	assert!(first::<X64>(b"\xC3").is_terminator());
+	assert!(first::<X86>(b"\xCC").is_terminator());
+	// jmp qword ptr [rax]
+	assert!(first::<X64>(b"\xFF\x20").is_terminator());
+	// call rel32, call qword ptr [rax]
+	assert!(!first::<X64>(b"\xE8****").is_terminator());
+	assert!(first::<X64>(b"\xE8****").is_terminator_or_call());
+	assert!(!first::<X64>(b"\xFF\x10").is_terminator());
+	assert!(first::<X64>(b"\xFF\x10").is_terminator_or_call());
+	// syscall, sysret
+	assert!(!first::<X64>(b"\x0F\x05").is_terminator());
+	assert!(first::<X64>(b"\x0F\x05").is_terminator_or_call());
+	assert!(first::<X64>(b"\x0F\x07").is_terminator());
+	// inc dword ptr [eax], push esi
+	assert!(!first::<X86>(b"\xFF\x00").is_terminator_or_call());
+	assert!(!first::<X86>(b"\x56").is_terminator_or_call());
+}
+
+#[test]
+fn classify() {
+	// call rel32, call qword ptr [rax]
+	assert!(first::<X64>(b"\xE8****").is_call());
+	assert!(first::<X64>(b"\xFF\x10").is_call());
+	// jmp rel8, jmp qword ptr [rax]
+	assert!(first::<X64>(b"\xEB*").is_jmp());
+	assert!(first::<X64>(b"\xFF\x20").is_jmp());
+	// je rel8, je rel32
+	assert!(first::<X86>(b"\x74*").is_conditional_jmp());
+	assert!(first::<X86>(b"\x0F\x84****").is_conditional_jmp());
+	assert!(!first::<X86>(b"\x74*").is_jmp());
+	// ret, retf imm16
+	assert!(first::<X86>(b"\xC3").is_ret());
+	assert!(first::<X86>(b"\xCA**").is_ret());
+	assert!(!first::<X86>(b"\xC3").is_call());
+	// sub esp, *; shl eax, 3; bt eax, *; push es
+	assert_eq!(first::<X86>(b"\x83\xEC*").group_index(), Some(5));
+	assert_eq!(first::<X86>(b"\xC1\xE0\x03").group_index(), Some(4));
+	assert_eq!(first::<X86>(b"\x0F\xBA\xE0*").group_index(), Some(4));
+	assert_eq!(first::<X86>(b"\x06").group_index(), None);
+	// bnd ret, bnd jmp rel32
+	assert!(first::<X64>(b"\xF2\xC3").is_ret());
+	assert_eq!(first::<X64>(b"\xF2\xE9\x10\x00\x00\x00").branch_target(), Some(0x16));
+}
+
+#[test]
+fn memory() {
+	// mov eax, dword ptr [ebx]
+	assert!(first::<X86>(b"\x8B\x03").accesses_memory());
+	// mov eax, ebx
+	assert!(!first::<X86>(b"\x8B\xC3").accesses_memory());
+	// lea rax, [rip+****]
+	assert!(!first::<X64>(b"\x48\x8D\x05****").accesses_memory());
+	// mov eax, dword ptr fs:****
+	assert!(first::<X86>(b"\x64\xA1****").accesses_memory());
+	// mov eax, 0x01010101
+	assert!(!first::<X86>(b"\xB8\x01\x01\x01\x01").accesses_memory());
+}
+
+#[test]
+fn sign_extended() {
+	// add dword ptr [eax], -1
+	assert!(first::<X86>(b"\x83\x00\xFF").imm_is_sign_extended());
+	// push -1
+	assert!(first::<X86>(b"\x6A\xFF").imm_is_sign_extended());
+	// mov rax, -1
+	assert!(first::<X64>(b"\x48\xC7\xC0\xFF\xFF\xFF\xFF").imm_is_sign_extended());
+	// test rax, ****
+	assert!(first::<X64>(b"\x48\xF7\xC0****").imm_is_sign_extended());
+	// not rax
+	assert!(!first::<X64>(b"\x48\xF7\xD0").imm_is_sign_extended());
+	// mov al, 0xff
+	assert!(!first::<X86>(b"\xB0\xFF").imm_is_sign_extended());
+	// mov eax, 0xffffffff
+	assert!(!first::<X64>(b"\xB8\xFF\xFF\xFF\xFF").imm_is_sign_extended());
+}
+
+#[test]
+fn rip_relative() {
+	// lea rax, [rip+0x10]
+	let inst = X64::iter(b"\x48\x8D\x05\x10\x00\x00\x00", 0x1000).next().unwrap();
+	assert_eq!(inst.rip_target(), Some(0x1000 + 7 + 0x10));
+	// lea rax, [eip+0x10]
+	let inst = X64::iter(b"\x67\x48\x8D\x05\x10\x00\x00\x00", 0x1000).next().unwrap();
+	assert_eq!(inst.rip_target(), Some(0x1000 + 8 + 0x10));
+	let inst = X64::iter(b"\x67\x48\x8D\x05\x10\x00\x00\x00", 0xFFFF_FFF8).next().unwrap();
+	assert_eq!(inst.rip_target(), Some(0x10));
+	let inst = X64::iter(b"\x67\x48\x8D\x05\x10\x00\x00\x00", 0xFFFF_FFFF_FFFF_FFD8).next().unwrap();
+	assert_eq!(inst.rip_target(), Some(0xFFFF_FFF0));
+	// mov eax, dword ptr [rip-0x10]
+	let inst = X64::iter(b"\x8B\x05\xF0\xFF\xFF\xFF", 0x1000).next().unwrap();
+	assert_eq!(inst.rip_target(), Some(0x1000 + 6 - 0x10));
+	// cmp dword ptr [rip+0x10], 1
+	let inst = X64::iter(b"\x83\x3D\x10\x00\x00\x00\x01", 0x1000).next().unwrap();
+	assert_eq!(inst.rip_target(), Some(0x1000 + 7 + 0x10));
+	// lea eax, ds:[0x10]
+	assert_eq!(first::<X86>(b"\x8D\x05\x10\x00\x00\x00").rip_target(), None);
+	// mov eax, 0x10
+	assert_eq!(first::<X64>(b"\xB8\x05\x00\x00\x00").rip_target(), None);
+}
+
+#[test]
+fn displacement() {
+	// mov eax, dword ptr [ebp+0x10]
+	let inst = first::<X86>(b"\x8B\x45\x10");
+	assert_eq!((inst.disp_offset(), inst.disp_size()), (Some(2), Some(1)));
+	// mov eax, dword ptr ds:[0x401000]
+	let inst = first::<X86>(b"\x8B\x05\x00\x10\x40\x00");
+	assert_eq!((inst.disp_offset(), inst.disp_size()), (Some(2), Some(4)));
+	assert_eq!(inst.read::<u32>(2), 0x401000);
+	// mov eax, dword ptr [rsp+rcx*4+0x10], mov eax, dword ptr [rcx*4+0x10]
+	let inst = first::<X64>(b"\x8B\x44\x8C\x10");
+	assert_eq!((inst.disp_offset(), inst.disp_size()), (Some(3), Some(1)));
+	let inst = first::<X64>(b"\x8B\x04\x8D\x10\x00\x00\x00");
+	assert_eq!((inst.disp_offset(), inst.disp_size()), (Some(3), Some(4)));
+	// mov ax, word ptr [bp+si+0x1000]
+	let inst = first::<X86>(b"\x66\x67\x8B\x82\x00\x10");
+	assert_eq!((inst.disp_offset(), inst.disp_size()), (Some(4), Some(2)));
+	// mov eax, dword ptr [ebx], mov eax, ebx
+	assert_eq!(first::<X86>(b"\x8B\x03").disp_offset(), None);
+	assert_eq!(first::<X86>(b"\x8B\xC3").disp_offset(), None);
+}
+
+#[test]
+fn immediate() {
+	// add dword ptr [ebp-4], 1
+	let inst = first::<X86>(b"\x83\x45\xFC\x01");
+	assert_eq!((inst.imm_offset(), inst.imm_size()), (Some(3), Some(1)));
+	// mov dword ptr [rsp+rcx*4], 0x1234
+	let inst = first::<X64>(b"\xC7\x04\x8C\x34\x12\x00\x00");
+	assert_eq!((inst.imm_offset(), inst.imm_size()), (Some(3), Some(4)));
+	assert_eq!(inst.try_read::<u32>(3), Some(0x1234));
+	assert_eq!(inst.try_read::<u64>(3), None);
+	// mov rax, 0x1234
+	let inst = first::<X64>(b"\x48\xB8\x34\x12\x00\x00\x00\x00\x00\x00");
+	assert_eq!((inst.imm_offset(), inst.imm_size()), (Some(2), Some(8)));
+	// mov eax, dword ptr ds:[0x401000], add eax, dword ptr [ebp-4], nop
+	assert_eq!(first::<X86>(b"\xA1\x00\x10\x40\x00").imm_offset(), None);
+	assert_eq!(first::<X86>(b"\x03\x45\xFC").imm_offset(), None);
+	assert_eq!(first::<X86>(b"\x90").imm_offset(), None);
+}
+
+#[test]
+fn reg_form() {
+	// The ModRM mod field is ignored, these have no SIB byte, displacement or memory operand
+	fn check<X: Isa>(bytes: &[u8]) {
+		let inst = first::<X>(bytes);
+		assert_eq!((inst.bytes().len(), inst.modrm()), (3, Some(bytes[2])));
+		assert_eq!((inst.sib(), inst.disp_offset(), inst.disp_size()), (None, None, None));
+		assert_eq!((inst.imm_offset(), inst.imm_size()), (None, None));
+		assert!(inst.rip_target().is_none());
+		assert!(!inst.accesses_memory());
+	}
+	// mov rbp, cr0; mov rax, cr0; mov cr0, rsp; mov esp, cr0
+	check::<X64>(b"\x0F\x20\x05");
+	check::<X64>(b"\x0F\x20\x80");
+	check::<X64>(b"\x0F\x22\x44");
+	check::<X86>(b"\x0F\x20\x84");
+	let info = X64::probe(b"\x0F\x20\x80", 0x1000).unwrap();
+	assert_eq!((info.len.total_len, info.mem), (3, false));
+	// mov rbp, cr0; push rbp; mov rbp, rsp; sub rsp, 0x20
+	let info = X64::hook_prologue(b"\x0F\x20\x05\x55\x48\x89\xE5\x48\x83\xEC\x20", 0x1000, 5).unwrap();
+	assert_eq!((info.len, info.count, info.needs_relocation), (7, 3, false));
+}
+
+#[test]
+fn branch_target() {
+	// call $-0x100
+	let inst = X64::iter(b"\xE8\xFB\xFE\xFF\xFF", 0x1000).next().unwrap();
+	assert_eq!(inst.branch_target(), Some(0x1000 - 0x100));
+	// jne $+0x20
+	let inst = X86::iter(b"\x0F\x85\x1A\x00\x00\x00", 0x1000).next().unwrap();
+	assert_eq!(inst.branch_target(), Some(0x1020));
+	// loop $-2
+	let inst = X86::iter(b"\xE2\xFE", 0x1000).next().unwrap();
+	assert_eq!(inst.branch_target(), Some(0x1000));
+	// jmp qword ptr [rip+0x10], ret
+	assert_eq!(first::<X64>(b"\xFF\x25\x10\x00\x00\x00").branch_target(), None);
+	assert_eq!(first::<X64>(b"\xC3").branch_target(), None);
+}
+
+#[test]
+fn prefixes() {
+	// lock add qword ptr [rax], rax
+	let prefixes = first::<X64>(b"\xF0\x48\x01\x00").prefixes();
+	assert_eq!(prefixes.bytes(), b"\xF0\x48");
+	assert_eq!(prefixes.rex(), Some(0x48));
+	assert!(prefixes.lock() && prefixes.rex_w() && !prefixes.rex_r() && !prefixes.rex_b());
+	// rex.b in front of the lock prefix is ignored
+	assert_eq!(first::<X64>(b"\x41\xF0\x01\x00").prefixes().rex(), None);
+	// rep movs byte ptr es:[edi], byte ptr fs:[esi]
+	let prefixes = first::<X86>(b"\xF3\x64\xA4").prefixes();
+	assert!(prefixes.rep() && !prefixes.repne());
+	assert_eq!(prefixes.segment(), Some(0x64));
+	// mov rax, qword ptr gs:[0x30]; mov eax, dword ptr [rax]
+	assert_eq!(first::<X64>(b"\x65\x48\x8B\x04\x25\x30\x00\x00\x00").segment(), Some(Segment::GS));
+	assert_eq!(first::<X64>(b"\x8B\x00").segment(), None);
+	// inc eax is not a REX prefix
+	assert_eq!(first::<X86>(b"\x40").prefixes().rex(), None);
+	assert_eq!(first::<X86>(b"\x40").rex(), None);
+	// add r8, rbx
+	assert_eq!(first::<X64>(b"\x49\x01\xD8").rex(), Some(0x49));
+}
+
+#[test]
+fn relocate() {
+	let mut out = [0; 8];
+	// lea rax, [rip+0x10]
+	let inst = X64::iter(b"\x48\x8D\x05\x10\x00\x00\x00", 0x1000).next().unwrap();
+	assert_eq!(inst.relocate(0x800, &mut out), Ok(()));
+	assert_eq!(&out[..7], b"\x48\x8D\x05\x10\x08\x00\x00");
+	assert_eq!(inst.relocate(0x1_0000_1000, &mut out), Err(OutOfRange));
+	assert_eq!(&out[..7], b"\x48\x8D\x05\x10\x08\x00\x00");
+	// lea eax, ds:[0x10]
+	let inst = X86::iter(b"\x8D\x05\x10\x00\x00\x00", 0x1000).next().unwrap();
+	assert_eq!(inst.relocate(0x800, &mut out), Ok(()));
+	assert_eq!(&out[..6], b"\x8D\x05\x10\x00\x00\x00");
+}
+
+#[test]
+#[allow(clippy::manual_div_ceil)]
+fn fill_nop() {
+	let mut code = [0xCC; 20];
+	for len in 1..code.len() {
+		X64::fill_nop(&mut code[..len]);
+		assert!(X64::iter(&code[..len], 0).all(|inst| inst.op_bytes() == b"\x90" || inst.op_bytes() == b"\x0F\x1F"));
+		assert_eq!(X64::iter(&code[..len], 0).count(), (len + 8) / 9);
+		assert_eq!(code[len], 0xCC);
+		X16::fill_nop(&mut code[..len]);
+		assert!(X16::decodes_exactly(&code[..len]));
+		assert_eq!(X16::iter(&code[..len], 0).count(), (len + 3) / 4);
+	}
+}
+
+#[test]
+fn encode_nop() {
+	let nops: [&[u8]; 11] = [
+		b"\x90",
+		b"\x66\x90",
+		b"\x0F\x1F\x00",
+		b"\x0F\x1F\x40\x00",
+		b"\x0F\x1F\x44\x00\x00",
+		b"\x66\x0F\x1F\x44\x00\x00",
+		b"\x0F\x1F\x80\x00\x00\x00\x00",
+		b"\x0F\x1F\x84\x00\x00\x00\x00\x00",
+		b"\x66\x0F\x1F\x84\x00\x00\x00\x00\x00",
+		b"\x66\x0F\x1F\x84\x00\x00\x00\x00\x00\x90",
+		b"\x66\x0F\x1F\x84\x00\x00\x00\x00\x00\x66\x90",
+	];
+	for (i, &nop) in nops.iter().enumerate() {
+		let mut out = [0xCC; 12];
+		::encode_nop(i + 1, &mut out);
+		assert_eq!(&out[..nop.len()], nop);
+		assert_eq!(out[nop.len()], 0xCC);
+		assert!(X86::decodes_exactly(nop) && X64::decodes_exactly(nop));
+	}
+}
+
+#[test]
+fn faults() {
+	// mov eax, dword ptr [ebx]
+	assert!(first::<X86>(b"\x8B\x03").may_fault());
+	// div ecx, idiv ecx
+	assert!(first::<X86>(b"\xF7\xF1").may_fault());
+	assert!(first::<X86>(b"\xF7\xF9").may_fault());
+	// rep movsb, hlt, ud2
+	assert!(first::<X64>(b"\xF3\xA4").may_fault());
+	assert!(first::<X64>(b"\xF4").may_fault());
+	assert!(first::<X64>(b"\x0F\x0B").may_fault());
+	// neg eax, add eax, ecx, lea rax, [rax]
+	assert!(!first::<X86>(b"\xF7\xD8").may_fault());
+	assert!(!first::<X86>(b"\x01\xC8").may_fault());
+	assert!(!first::<X64>(b"\x48\x8D\x00").may_fault());
+}
+
+#[test]
+fn atomics() {
+	// xchg dword ptr [eax], ebx
+	assert!(first::<X86>(b"\x87\x18").is_atomic());
+	// lock add dword ptr [eax], 1
+	assert!(first::<X86>(b"\xF0\x83\x00\x01").is_atomic());
+	// xchg eax, ebx
+	assert!(!first::<X86>(b"\x87\xD8").is_atomic());
+	// add dword ptr [eax], 1
+	assert!(!first::<X86>(b"\x83\x00\x01").is_atomic());
+}
+
+#[cfg(not(feature = "minimal"))]
+#[test]
+fn vex() {
+	// vzeroupper, not a jcc
+	assert_eq!(first::<X64>(b"\xC5\xF8\x77").flow(), Flow::Next);
+	// vmovdqu ymm0, ymmword ptr [rax+*]
+	assert!(first::<X64>(b"\xC5\xFE\x6F\x40*").accesses_memory());
+	// vaddps xmm0, xmm1, xmm2, the VEX prefix is not a lock prefix
+	let inst = first::<X86>(b"\xC5\xF0\x58\xC2");
+	assert_eq!(inst.prefix_bytes(), b"\xC5\xF0");
+	assert_eq!(inst.op_bytes(), b"\x58");
+	assert!(!inst.is_atomic());
+	assert!(!inst.accesses_memory());
+	// vmovups zmm0, zmmword ptr [rax+*]
+	let inst = first::<X64>(b"\x62\xF1\x7C\x48\x10\x40*");
+	assert_eq!(inst.prefix_bytes().len(), 4);
+	assert!(inst.accesses_memory());
+	assert_eq!(inst.opcode_map(), OpcodeMap::Evex);
+	// pshufb xmm0, xmm1; pclmulqdq xmm0, xmm1, *; vmovaps xmm0, xmm1
+	assert_eq!(first::<X64>(b"\x66\x0F\x38\x00\xC1").opcode_map(), OpcodeMap::ThreeByte38);
+	assert_eq!(first::<X64>(b"\x66\x0F\x3A\x44\xC1*").opcode_map(), OpcodeMap::ThreeByte3A);
+	assert_eq!(first::<X64>(b"\xC5\xF8\x28\xC1").opcode_map(), OpcodeMap::Vex);
+	// vpcmov xmm0, xmm1, xmm2, xmm3
+	let inst = first::<X64>(b"\x8F\xE8\x70\xA2\xC2\x30");
+	assert_eq!(inst.opcode_map(), OpcodeMap::Xop);
+	assert_eq!((inst.prefix_bytes().len(), inst.op_bytes()), (3, &b"\xA2"[..]));
+	assert_eq!((inst.modrm(), inst.imm_size()), (Some(0xC2), Some(1)));
+}
+
+#[cfg(not(feature = "minimal"))]
+#[test]
+fn ld_op() {
+	// pshufb xmm0, xmm1; pshufb xmm0, xmmword ptr [r8+1]
+	assert_eq!(X64::ld_op(b"\x66\x0F\x38\x00\xC1"), (5, &b"\x0F\x38\x00"[..]));
+	assert_eq!(X64::ld_op(b"\x66\x41\x0F\x38\x00\x40\x01"), (7, &b"\x0F\x38\x00"[..]));
+	assert_eq!(X86::ld_op(b"\x66\x0F\x38\x00\x40\x01"), (6, &b"\x0F\x38\x00"[..]));
+	// palignr xmm0, xmm1, 8
+	assert_eq!(X64::ld_op(b"\x66\x0F\x3A\x0F\xC1\x08"), (6, &b"\x0F\x3A\x0F"[..]));
+	assert_eq!(X64::ld_op(b"\x66\x0F\x38\x00"), (0, &b""[..]));
+}
+
+#[test]
+fn ranges() {
+	// lock add qword ptr [rax+*], *; mov eax, ****; ret
+	for &bytes in &[&b"\xF0\x48\x83\x40**"[..], b"\xB8****", b"\xC3"] {
+		let inst = first::<X64>(bytes);
+		let (prefix, op, arg) = X64::inst_len(bytes).ranges();
+		assert_eq!(&bytes[prefix], inst.prefix_bytes());
+		assert_eq!(&bytes[op], inst.op_bytes());
+		assert_eq!(&bytes[arg], inst.arg_bytes());
+	}
+}
+
+#[test]
+fn consistent() {
+	assert!(InstLen::EMPTY.is_consistent());
+	assert!(!InstLen { total_len: 3, op_len: 1, arg_len: 1, prefix_len: 0 }.is_consistent());
+	// overflowing u8
+	assert!(!InstLen { total_len: 0, op_len: 128, arg_len: 128, prefix_len: 0 }.is_consistent());
+}
+
+#[test]
+fn opcode_map() {
+	// nop
+	assert_eq!(first::<X64>(b"\x90").opcode_map(), OpcodeMap::Primary);
+	// rex push rbp
+	assert_eq!(first::<X64>(b"\x40\x55").opcode_map(), OpcodeMap::Primary);
+	// movzx eax, cl
+	assert_eq!(first::<X86>(b"\x0F\xB6\xC1").opcode_map(), OpcodeMap::Secondary);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde() {
+	use serde_test::{assert_tokens, Token};
+	// sub rsp, *
+	let len = X64::inst_len(b"\x48\x83\xEC*");
+	assert_tokens(&len, &[
+		Token::Struct { name: "InstLen", len: 4 },
+		Token::Str("total_len"), Token::U8(4),
+		Token::Str("op_len"), Token::U8(1),
+		Token::Str("arg_len"), Token::U8(2),
+		Token::Str("prefix_len"), Token::U8(1),
+		Token::StructEnd,
+	]);
+}
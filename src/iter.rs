@@ -1,9 +1,11 @@
-use core::{cmp, fmt, ops};
+use core::{cmp, fmt, iter, ops};
 use *;
 
 /// Length disassembler iterator.
 ///
 /// Instances are created by the [`Isa::iter`](trait.Isa.html#method.iter) method.
+///
+/// Not a [`FusedIterator`](https://doc.rust-lang.org/core/iter/trait.FusedIterator.html), iterating may continue after [`resync`](#method.resync) skips an invalid instruction.
 pub struct Iter<'a, X: Isa> {
 	/// The remaining bytes to length disassemble.
 	pub bytes: &'a [u8],
@@ -21,12 +23,139 @@ impl<'a, X: Isa> Clone for Iter<'a, X> {
 }
 
 impl<'a, X: Isa> Iter<'a, X> {
+	/// Gets the remaining bytes to length disassemble.
+	pub fn remaining(&self) -> &'a [u8] {
+		self.bytes
+	}
 	/// Consumes a number of bytes from the input.
 	pub fn consume(&mut self, n: usize) {
 		let n = cmp::min(n, self.bytes.len());
 		self.bytes = &self.bytes[n..];
 		self.va += X::as_va(n);
 	}
+	/// Returns the next instruction without advancing the iterator.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// let mut iter = X64::iter(b"\x40\x55\x48\x83\xEC*", 0x1000);
+	/// let peeked = iter.peek().map(|inst| (inst.va(), inst.bytes()));
+	/// assert_eq!(peeked, Some((0x1000, &b"\x40\x55"[..])));
+	/// assert_eq!(iter.next().map(|inst| (inst.va(), inst.bytes())), peeked);
+	/// assert_eq!(iter.va, 0x1002);
+	/// ```
+	pub fn peek(&self) -> Option<Inst<'a, X>> {
+		self.clone().next()
+	}
+	/// Pairs each instruction with its offset in the remaining bytes.
+	///
+	/// The offsets are relative to the bytes at the time of the call, unlike the virtual address of the instruction.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// let mut iter = X64::iter(b"\x40\x55\x48\x83\xEC*\x00\x80", 0x1000).enumerate_offsets();
+	/// assert_eq!(iter.next().map(|(offset, inst)| (offset, inst.va())), Some((0, 0x1000)));
+	/// assert_eq!(iter.next().map(|(offset, inst)| (offset, inst.va())), Some((2, 0x1002)));
+	/// assert!(iter.next().is_none());
+	/// ```
+	pub fn enumerate_offsets(self) -> Offsets<'a, X> {
+		Offsets { iter: self, offset: 0 }
+	}
+	/// Stops iterating at the given virtual address.
+	///
+	/// Yields the instructions starting before `end`.
+	/// An instruction starting before but straddling `end` is included.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// let code = b"\x56\x33\xF6\x57\xBF\xA0\x10\x40\x00\x85\xD2";
+	/// let vas: Vec<u32> = X86::iter(code, 0x1000).take_while_va(0x1005).map(|inst| inst.va()).collect();
+	/// assert_eq!(vas, [0x1000, 0x1001, 0x1003, 0x1004]);
+	/// ```
+	pub fn take_while_va(self, end: X::Va) -> TakeWhileVa<'a, X> {
+		TakeWhileVa { iter: self, end }
+	}
+	/// Pairs each instruction with the virtual address of the instruction following it.
+	///
+	/// The fallthrough address is where execution continues unless the instruction transfers control.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// let code = b"\x56\x33\xF6\x57\xBF\xA0\x10\x40\x00\x85\xD2\x74\x10\x8B\xF2\x8B\xFA";
+	/// let next: Vec<u32> = X86::iter(code, 0x1000).with_fallthrough().map(|(_, next)| next).collect();
+	/// assert_eq!(next, [0x1001, 0x1003, 0x1004, 0x1009, 0x100b, 0x100d, 0x100f, 0x1011]);
+	/// ```
+	pub fn with_fallthrough(self) -> Fallthrough<'a, X> {
+		Fallthrough { iter: self }
+	}
+	/// Formats the instructions with their virtual address.
+	///
+	/// One line per opcode.
+	/// Alternate flag to put spaces between the bytes.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// let iter = X64::iter(b"\x40\x55\x48\x83\xEC*\x00\x80", 0x1000);
+	/// assert_eq!(format!("{}", iter.annotated()), "1000: 4055\n1002: 4883ec2a\n");
+	/// assert_eq!(format!("{:#}", iter.annotated()), "1000: 40 55\n1002: 48 83 ec 2a\n");
+	/// ```
+	pub fn annotated(&self) -> Annotated<'a, X> {
+		Annotated { iter: self.clone() }
+	}
+	/// Yields every byte, either as part of an instruction or as a single skipped byte where decoding fails.
+	///
+	/// Sweeps linearly over the bytes until they are exhausted, see [`resync`](#method.resync).
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // push rbp; push es, invalid in 64-bit mode; ret
+	/// let items: Vec<_> = X64::iter(b"\x55\x06\xC3", 0x1000).lossy().map(|item| item.map(|inst| inst.va())).collect();
+	/// assert_eq!(items, [Ok(0x1000), Err(0x06), Ok(0x1002)]);
+	/// ```
+	pub fn lossy(self) -> Lossy<'a, X> {
+		Lossy { iter: self }
+	}
+	/// Consumes all the instructions.
+	///
+	/// Returns the virtual address past the last instruction and the bytes which failed to decode.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// let (va, tail) = X64::iter(b"\x40\x55\x48\x83\xEC*\x00\x80", 0x1000).decode_all();
+	/// assert_eq!(va, 0x1006);
+	/// assert_eq!(tail, b"\x00\x80");
+	/// ```
+	pub fn decode_all(mut self) -> (X::Va, &'a [u8]) {
+		for _ in self.by_ref() {}
+		(self.va, self.bytes)
+	}
+	/// Skips a byte if the remaining bytes start with an invalid instruction.
+	///
+	/// Returns whether iterating may continue, which is not the case when the remaining bytes are empty or truncated.
+	/// Allows best-effort scanning of bytes with code and data mixed together.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // push rbp; data which is invalid in 64-bit mode; ret
+	/// let mut iter = X64::iter(b"\x55\x06\x07\x0E\xC3", 0x1000);
+	/// let mut vas = Vec::new();
+	/// loop {
+	/// 	vas.extend(iter.by_ref().map(|inst| inst.va()));
+	/// 	if !iter.resync() {
+	/// 		break;
+	/// 	}
+	/// }
+	/// assert_eq!(vas, [0x1000, 0x1004]);
+	/// ```
+	pub fn resync(&mut self) -> bool {
+		match X::try_inst_len(self.bytes) {
+			Ok(_) => true,
+			Err(LenError::Invalid) => {
+				self.consume(1);
+				!self.bytes.is_empty()
+			},
+			Err(LenError::Truncated { .. }) => false,
+		}
+	}
 }
 
 impl<'a, X: Isa> Iterator for Iter<'a, X> {
@@ -43,6 +172,190 @@ impl<'a, X: Isa> Iterator for Iter<'a, X> {
 			None
 		}
 	}
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		// Every instruction is at least one byte
+		(0, Some(self.bytes.len()))
+	}
+}
+
+/// Iterator over instructions and their offset.
+///
+/// Instances are created by the [`Iter::enumerate_offsets`](struct.Iter.html#method.enumerate_offsets) method.
+pub struct Offsets<'a, X: Isa> {
+	iter: Iter<'a, X>,
+	offset: usize,
+}
+
+impl<'a, X: Isa> Clone for Offsets<'a, X> {
+	fn clone(&self) -> Self {
+		Offsets {
+			iter: self.iter.clone(),
+			offset: self.offset,
+		}
+	}
+}
+
+impl<'a, X: Isa> Iterator for Offsets<'a, X> {
+	type Item = (usize, Inst<'a, X>);
+	fn next(&mut self) -> Option<(usize, Inst<'a, X>)> {
+		let inst = self.iter.next()?;
+		let offset = self.offset;
+		self.offset += inst.bytes().len();
+		Some((offset, inst))
+	}
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.iter.size_hint()
+	}
+}
+
+impl<'a, X: Isa> iter::FusedIterator for Offsets<'a, X> {}
+
+/// Iterator over instructions up to a virtual address.
+///
+/// Instances are created by the [`Iter::take_while_va`](struct.Iter.html#method.take_while_va) method.
+pub struct TakeWhileVa<'a, X: Isa> {
+	iter: Iter<'a, X>,
+	end: X::Va,
+}
+
+impl<'a, X: Isa> Clone for TakeWhileVa<'a, X> {
+	fn clone(&self) -> Self {
+		TakeWhileVa {
+			iter: self.iter.clone(),
+			end: self.end,
+		}
+	}
+}
+
+impl<'a, X: Isa> Iterator for TakeWhileVa<'a, X> {
+	type Item = Inst<'a, X>;
+	fn next(&mut self) -> Option<Inst<'a, X>> {
+		if self.iter.va >= self.end {
+			return None;
+		}
+		self.iter.next()
+	}
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		if self.iter.va >= self.end { (0, Some(0)) } else { self.iter.size_hint() }
+	}
+}
+
+impl<'a, X: Isa> iter::FusedIterator for TakeWhileVa<'a, X> {}
+
+/// Iterator over instructions and their fallthrough address.
+///
+/// Instances are created by the [`Iter::with_fallthrough`](struct.Iter.html#method.with_fallthrough) method.
+pub struct Fallthrough<'a, X: Isa> {
+	iter: Iter<'a, X>,
+}
+
+impl<'a, X: Isa> Clone for Fallthrough<'a, X> {
+	fn clone(&self) -> Self {
+		Fallthrough {
+			iter: self.iter.clone(),
+		}
+	}
+}
+
+impl<'a, X: Isa> Iterator for Fallthrough<'a, X> {
+	type Item = (Inst<'a, X>, X::Va);
+	fn next(&mut self) -> Option<(Inst<'a, X>, X::Va)> {
+		let inst = self.iter.next()?;
+		Some((inst, self.iter.va))
+	}
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.iter.size_hint()
+	}
+}
+
+impl<'a, X: Isa> iter::FusedIterator for Fallthrough<'a, X> {}
+
+/// Iterator over instructions and skipped bytes.
+///
+/// Instances are created by the [`Iter::lossy`](struct.Iter.html#method.lossy) method.
+pub struct Lossy<'a, X: Isa> {
+	iter: Iter<'a, X>,
+}
+
+impl<'a, X: Isa> Clone for Lossy<'a, X> {
+	fn clone(&self) -> Self {
+		Lossy {
+			iter: self.iter.clone(),
+		}
+	}
+}
+
+impl<'a, X: Isa> Iterator for Lossy<'a, X> {
+	type Item = Result<Inst<'a, X>, u8>;
+	fn next(&mut self) -> Option<Result<Inst<'a, X>, u8>> {
+		if let Some(inst) = self.iter.next() {
+			return Some(Ok(inst));
+		}
+		let &byte = self.iter.bytes.first()?;
+		self.iter.consume(1);
+		Some(Err(byte))
+	}
+	#[allow(clippy::manual_div_ceil)]
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		// Every instruction is at most 15 bytes
+		((self.iter.bytes.len() + 14) / 15, Some(self.iter.bytes.len()))
+	}
+}
+
+impl<'a, X: Isa> iter::FusedIterator for Lossy<'a, X> {}
+
+/// Iterator over the byte ranges of instructions.
+///
+/// Instances are created by the [`Isa::pattern_spans`](trait.Isa.html#method.pattern_spans) method.
+pub struct Spans<'a, X: Isa> {
+	pub(crate) iter: Offsets<'a, X>,
+}
+
+impl<'a, X: Isa> Clone for Spans<'a, X> {
+	fn clone(&self) -> Self {
+		Spans {
+			iter: self.iter.clone(),
+		}
+	}
+}
+
+impl<'a, X: Isa> Iterator for Spans<'a, X> {
+	type Item = ops::Range<usize>;
+	fn next(&mut self) -> Option<ops::Range<usize>> {
+		let (offset, inst) = self.iter.next()?;
+		Some(offset..offset + inst.bytes().len())
+	}
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.iter.size_hint()
+	}
+}
+
+impl<'a, X: Isa> iter::FusedIterator for Spans<'a, X> {}
+
+/// Formats instructions with their virtual address.
+///
+/// Instances are created by the [`Iter::annotated`](struct.Iter.html#method.annotated) method.
+pub struct Annotated<'a, X: Isa> {
+	iter: Iter<'a, X>,
+}
+
+impl<'a, X: Isa> Clone for Annotated<'a, X> {
+	fn clone(&self) -> Self {
+		Annotated {
+			iter: self.iter.clone(),
+		}
+	}
+}
+
+impl<'a, X: Isa> fmt::Display for Annotated<'a, X> where X::Va: fmt::LowerHex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for inst in self.iter.clone() {
+			write!(f, "{:x}: ", inst.va())?;
+			fmt_bytes(inst.bytes(), b'a', f)?;
+			f.write_str("\n")?;
+		}
+		Ok(())
+	}
 }
 
 impl<'a, X: Isa> ops::Deref for Iter<'a, X> {
@@ -58,13 +371,37 @@ impl<'a, X: Isa> ops::Deref for Iter<'a, X> {
 /// Alternate flag to put spaces between the bytes.
 impl<'a, X: Isa> fmt::Debug for Iter<'a, X> {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.fmt_grouped(b'a', f)
+	}
+}
+
+/// Uppercase hex formatter.
+///
+/// Same layout as the debug formatter.
+impl<'a, X: Isa> fmt::UpperHex for Iter<'a, X> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.fmt_grouped(b'A', f)
+	}
+}
+
+/// Lowercase hex formatter.
+///
+/// Same layout as the debug formatter.
+impl<'a, X: Isa> fmt::LowerHex for Iter<'a, X> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.fmt_grouped(b'a', f)
+	}
+}
+
+impl<'a, X: Isa> Iter<'a, X> {
+	fn fmt_grouped(&self, hex_char: u8, f: &mut fmt::Formatter) -> fmt::Result {
 		let mut iter = self.clone();
-		while let Some(inst) = iter.next() {
+		for inst in iter.by_ref() {
 			f.write_str("[")?;
-			fmt_bytes(inst.bytes(), b'a', f)?;
+			fmt_bytes(inst.bytes(), hex_char, f)?;
 			f.write_str("] ")?;
 		}
-		fmt_bytes(iter.bytes, b'a', f)
+		fmt_bytes(iter.bytes, hex_char, f)
 	}
 }
 
@@ -81,3 +418,38 @@ impl<'a, X: Isa> fmt::Display for Iter<'a, X> {
 		Ok(())
 	}
 }
+
+//----------------------------------------------------------------
+
+#[test]
+fn fused() {
+	// push rbp; sub rsp, 0x2a; truncated
+	let mut iter = X64::iter(b"\x40\x55\x48\x83\xEC*\x00\x80", 0x1000).enumerate_offsets();
+	assert_eq!(iter.by_ref().count(), 2);
+	for _ in 0..3 {
+		assert!(iter.next().is_none());
+	}
+	// push rbp; push es, invalid in 64-bit mode; ret
+	let mut iter = X64::iter(b"\x55\x06\xC3", 0x1000);
+	assert_eq!(iter.by_ref().count(), 1);
+	for _ in 0..3 {
+		assert!(iter.next().is_none());
+		assert_eq!(iter.va, 0x1001);
+	}
+	// the iterator itself is not fused
+	assert!(iter.resync());
+	assert_eq!(iter.next().map(|inst| inst.va()), Some(0x1002));
+}
+
+#[test]
+fn size_hint() {
+	// push rbp; sub rsp, 0x2a; truncated
+	let mut iter = X64::iter(b"\x40\x55\x48\x83\xEC*\x00\x80", 0x1000);
+	assert_eq!(iter.size_hint(), (0, Some(8)));
+	iter.next();
+	assert_eq!(iter.size_hint(), (0, Some(6)));
+	iter.next();
+	assert_eq!(iter.size_hint(), (0, Some(2)));
+	assert_eq!(iter.remaining(), b"\x00\x80");
+	assert_eq!(iter.take_while_va(0x1006).size_hint(), (0, Some(0)));
+}
@@ -2,10 +2,17 @@
 Length Disassembler
 ===================
 
-Supports `x86` and `x86_64` up to `SSE4.2`.
+Supports `x86` (including its 16-bit mode) and `x86_64` up to `SSE4.2`.
 
 Valid opcodes will be length disassembled correctly. Invalid opcodes may be rejected on a best-effort basis.
 
+The `minimal` feature compiles out support for the MMX, 3DNow!, SSE, AVX and FPU instructions for a smaller footprint.
+Minimal builds fail to length disassemble any of these instructions.
+
+The `alloc` feature adds conveniences returning a `Vec`.
+
+The `serde` feature implements `Serialize` and `Deserialize` for `InstLen`.
+
 ## Examples
 
 Gets the length of the first opcode in a byte slice:
@@ -31,7 +38,7 @@ for inst in X64::iter(code, 0x1000) {
 }
 
 // 1000: 4055
-// 1002: 4883EC2A
+// 1002: 4883ec2a
 ```
 
 Find the opcode boundary after a minimum of 5 bytes:
@@ -76,19 +83,43 @@ assert_eq!(format!("{:#?}", iter), "[40 55] [48 83 ec 2a] 00 80");
 assert_eq!(format!("{:}", iter), "4055\n4883ec2a\n");
 assert_eq!(format!("{:#}", iter), "40 55\n48 83 ec 2a\n");
 ```
+
+The hex formatters use the `Debug` layout in the requested case:
+
+```
+use lde::{Isa, X64};
+let iter = X64::iter(b"\x40\x55\x48\x83\xEC*\x00\x80", 0);
+
+assert_eq!(format!("{:X}", iter), "[4055] [4883EC2A] 0080");
+assert_eq!(format!("{:#X}", iter), "[40 55] [48 83 EC 2A] 00 80");
+assert_eq!(format!("{:x}", iter), "[4055] [4883ec2a] 0080");
+```
 */
 
 #![no_std]
-use core::{fmt, mem, ops, ptr, str};
+#![allow(clippy::tabs_in_doc_comments)]
+use core::{cmp, fmt, mem, num, ops, ptr, str};
 
 #[cfg(test)]
 #[macro_use]
 extern crate std;
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_test;
+
 mod contains;
 
 mod iter;
-pub use self::iter::Iter;
+pub use self::iter::{Annotated, Fallthrough, Iter, Lossy, Offsets, Spans, TakeWhileVa};
+
+mod stream;
+pub use self::stream::StreamDecoder;
 
 mod x86;
 mod x64;
@@ -101,6 +132,10 @@ pub use self::inst::*;
 /// Defines a type which can be safely constructed from a byte array of the same size.
 ///
 /// Used to allow reading/writing immediates and displacements.
+///
+/// # Safety
+///
+/// Every bit pattern of the right size must be a valid value of the type.
 pub unsafe trait Int: Copy + 'static {}
 unsafe impl Int for u8 {}
 unsafe impl Int for u16 {}
@@ -132,6 +167,25 @@ pub fn read<T: Int>(bytes: &[u8], offset: usize) -> T {
 	let p = bytes[offset..offset + mem::size_of::<T>()].as_ptr() as *const T;
 	unsafe { ptr::read_unaligned(p) }
 }
+/// Helps reading immediate and displacement values without panicking.
+///
+/// Returns `None` if `offset..offset + sizeof(T)` is out of bounds.
+///
+/// # Examples
+///
+/// ```
+/// // mov eax, 0x01010101
+/// assert_eq!(lde::try_read::<u32>(b"\xB8\x01\x01\x01\x01", 1), Some(0x01010101));
+/// // truncated
+/// assert_eq!(lde::try_read::<u32>(b"\xB8\x01", 1), None);
+/// ```
+pub fn try_read<T: Int>(bytes: &[u8], offset: usize) -> Option<T> {
+	let end = offset.checked_add(mem::size_of::<T>())?;
+	if end > bytes.len() {
+		return None;
+	}
+	Some(read(bytes, offset))
+}
 /// Helps writing immediate and displacement values.
 ///
 /// # Examples
@@ -154,6 +208,203 @@ pub fn write<T: Int>(bytes: &mut [u8], offset: usize, val: T) -> &mut [u8] {
 	unsafe { ptr::write_unaligned(p, val); }
 	bytes
 }
+/// Helps reading immediate and displacement values at a fixed offset.
+///
+/// # Examples
+///
+/// ```
+/// // mov eax, 0x01010101
+/// let opcode = b"\xB8\x01\x01\x01\x01";
+///
+/// // reads the immedate value
+/// let result: u32 = lde::read_at::<_, 1>(opcode);
+///
+/// assert_eq!(result, 0x01010101);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `OFF..OFF + sizeof(T)` is out of bounds.
+#[inline]
+pub fn read_at<T: Int, const OFF: usize>(bytes: &[u8]) -> T {
+	read(bytes, OFF)
+}
+/// Helps writing immediate and displacement values at a fixed offset.
+///
+/// # Examples
+///
+/// ```
+/// // mov al, 1
+/// let mut opcode = [0xb0, 0x01];
+///
+/// // change the immediate to 0xff
+/// lde::write_at::<_, 1>(&mut opcode, 0xff_u8);
+///
+/// assert_eq!(opcode, [0xb0, 0xff]);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `OFF..OFF + sizeof(T)` is out of bounds.
+#[inline]
+pub fn write_at<T: Int, const OFF: usize>(bytes: &mut [u8], val: T) -> &mut [u8] {
+	write(bytes, OFF, val)
+}
+
+// The recommended multi-byte NOP encodings, indexed by length minus one.
+static NOPS: [&[u8]; 9] = [
+	b"\x90",
+	b"\x66\x90",
+	b"\x0F\x1F\x00",
+	b"\x0F\x1F\x40\x00",
+	b"\x0F\x1F\x44\x00\x00",
+	b"\x66\x0F\x1F\x44\x00\x00",
+	b"\x0F\x1F\x80\x00\x00\x00\x00",
+	b"\x0F\x1F\x84\x00\x00\x00\x00\x00",
+	b"\x66\x0F\x1F\x84\x00\x00\x00\x00\x00",
+];
+
+/// Writes `len` bytes of NOP instructions to the output.
+///
+/// Uses the recommended multi-byte NOP encodings of up to 9 bytes, longer lengths are covered by multiple NOPs.
+/// The encodings are valid in 32-bit and 64-bit mode, see [`Isa::fill_nop`](trait.Isa.html#method.fill_nop) for the 16-bit mode.
+///
+/// # Examples
+///
+/// ```
+/// let mut out = [0xCC; 12];
+/// lde::encode_nop(11, &mut out);
+/// assert_eq!(&out, b"\x66\x0F\x1F\x84\x00\x00\x00\x00\x00\x66\x90\xCC");
+/// ```
+///
+/// # Panics
+///
+/// Panics if `len` is larger than the output.
+pub fn encode_nop(len: usize, out: &mut [u8]) {
+	fill_nop(&mut out[..len], NOPS.len());
+}
+fn fill_nop(mut bytes: &mut [u8], max: usize) {
+	while !bytes.is_empty() {
+		let nop = NOPS[cmp::min(bytes.len(), max) - 1];
+		let (head, tail) = bytes.split_at_mut(nop.len());
+		head.copy_from_slice(nop);
+		bytes = tail;
+	}
+}
+
+/// Displacement does not fit in the encoding.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OutOfRange;
+
+/// Helps writing the displacement of short (rel8) branches.
+///
+/// Writes the displacement as a signed byte if it fits, otherwise the bytes are left untouched.
+///
+/// # Examples
+///
+/// ```
+/// // jmp $+0x12
+/// let mut opcode = [0xeb, 0x10];
+///
+/// assert_eq!(lde::write_rel8_checked(&mut opcode, 1, -0x80), Ok(()));
+/// assert_eq!(opcode, [0xeb, 0x80]);
+///
+/// assert_eq!(lde::write_rel8_checked(&mut opcode, 1, 0x80), Err(lde::OutOfRange));
+/// assert_eq!(opcode, [0xeb, 0x80]);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `offset` is out of bounds.
+pub fn write_rel8_checked(bytes: &mut [u8], offset: usize, disp: i32) -> Result<(), OutOfRange> {
+	if disp < i8::MIN as i32 || disp > i8::MAX as i32 {
+		return Err(OutOfRange);
+	}
+	write(bytes, offset, disp as i8);
+	Ok(())
+}
+
+/// Assembles a near jmp (`E9` rel32) located at `from_va` to `to_va`.
+///
+/// Fails if the target is more than 2 GiB away from the end of the jmp.
+///
+/// # Examples
+///
+/// ```
+/// // forward and backward
+/// assert_eq!(lde::jmp_rel32(0x1000, 0x2000), Ok(*b"\xE9\xFB\x0F\x00\x00"));
+/// assert_eq!(lde::jmp_rel32(0x2000, 0x1000), Ok(*b"\xE9\xFB\xEF\xFF\xFF"));
+///
+/// assert_eq!(lde::jmp_rel32(0x1000, 0x1_0000_2000), Err(lde::OutOfRange));
+/// ```
+pub fn jmp_rel32(from_va: u64, to_va: u64) -> Result<[u8; 5], OutOfRange> {
+	rel32(0xE9, from_va, to_va)
+}
+/// Assembles a near call (`E8` rel32) located at `from_va` to `to_va`.
+///
+/// Fails if the target is more than 2 GiB away from the end of the call.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(lde::call_rel32(0x1000, 0x1005), Ok(*b"\xE8\x00\x00\x00\x00"));
+/// assert_eq!(lde::call_rel32(0x1000, 0x1000), Ok(*b"\xE8\xFB\xFF\xFF\xFF"));
+/// ```
+pub fn call_rel32(from_va: u64, to_va: u64) -> Result<[u8; 5], OutOfRange> {
+	rel32(0xE8, from_va, to_va)
+}
+fn rel32(op: u8, from_va: u64, to_va: u64) -> Result<[u8; 5], OutOfRange> {
+	let disp = to_va.wrapping_sub(from_va.wrapping_add(5)) as i64;
+	if disp < i32::MIN as i64 || disp > i32::MAX as i64 {
+		return Err(OutOfRange);
+	}
+	let mut code = [op, 0, 0, 0, 0];
+	write(&mut code, 1, disp as i32);
+	Ok(code)
+}
+
+/// Parses a hex string into bytes.
+///
+/// Whitespace between the hex digits is ignored. Returns the number of bytes written to the output.
+///
+/// # Examples
+///
+/// ```
+/// use lde::{Isa, X64};
+/// let mut code = [0; 4];
+/// assert_eq!(lde::parse_hex("40 55 48 83", &mut code), 4);
+/// assert_eq!(&code, b"\x40\x55\x48\x83");
+/// // push rbp
+/// assert_eq!(X64::ld(&code), 2);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the string contains characters other than hex digits and whitespace, if a byte is missing its second hex digit or if the output is too small.
+pub fn parse_hex(s: &str, out: &mut [u8]) -> usize {
+	let mut len = 0;
+	let mut hi = None;
+	for chr in s.bytes() {
+		if chr.is_ascii_whitespace() {
+			continue;
+		}
+		let digit = match chr {
+			b'0'..=b'9' => chr - b'0',
+			b'a'..=b'f' => chr - b'a' + 10,
+			b'A'..=b'F' => chr - b'A' + 10,
+			_ => panic!("invalid hex digit: {:?}", chr as char),
+		};
+		match hi.take() {
+			None => hi = Some(digit),
+			Some(hi) => {
+				out[len] = hi << 4 | digit;
+				len += 1;
+			},
+		}
+	}
+	assert!(hi.is_none(), "odd number of hex digits");
+	len
+}
 
 #[inline]
 fn fmt_bytes(bytes: &[u8], hex_char: u8, f: &mut fmt::Formatter) -> fmt::Result {
@@ -177,9 +428,32 @@ fn fmt_bytes(bytes: &[u8], hex_char: u8, f: &mut fmt::Formatter) -> fmt::Result
 //----------------------------------------------------------------
 
 /// Virtual address type.
-pub trait Va: Copy + Ord + ops::Add<Output = Self> + ops::AddAssign {}
-impl Va for u32 {}
-impl Va for u64 {}
+pub trait Va: Copy + Ord + ops::Add<Output = Self> + ops::AddAssign {
+	#[doc(hidden)]
+	fn offset(self, rel: i64) -> Self;
+	#[doc(hidden)]
+	fn delta(self, base: Self) -> i64;
+}
+impl Va for u32 {
+	#[doc(hidden)]
+	fn offset(self, rel: i64) -> u32 {
+		self.wrapping_add(rel as u32)
+	}
+	#[doc(hidden)]
+	fn delta(self, base: u32) -> i64 {
+		self.wrapping_sub(base) as i32 as i64
+	}
+}
+impl Va for u64 {
+	#[doc(hidden)]
+	fn offset(self, rel: i64) -> u64 {
+		self.wrapping_add(rel as u64)
+	}
+	#[doc(hidden)]
+	fn delta(self, base: u64) -> i64 {
+		self.wrapping_sub(base) as i64
+	}
+}
 
 /// Instruction set architecture.
 ///
@@ -187,22 +461,378 @@ impl Va for u64 {}
 pub trait Isa: Sized {
 	/// Virtual address type.
 	type Va: Va;
+	/// Default address size of the instruction set.
+	///
+	/// The address size override prefix selects 32-bit addressing in 64-bit mode and toggles between 16-bit and 32-bit addressing otherwise.
+	///
+	/// ```
+	/// use lde::{Isa, Size, X16, X64};
+	/// assert_eq!(X16::ADDR_SIZE, Size::Bits16);
+	/// assert_eq!(X64::ADDR_SIZE, Size::Bits64);
+	/// ```
+	const ADDR_SIZE: Size;
 	/// Returns the length of the first opcode in the given byte slice.
 	///
 	/// When length disassembling fails, eg. the byte slice does not contain a complete and valid instruction, the return value is `0`.
 	fn ld(bytes: &[u8]) -> u32 {
 		Self::inst_len(bytes).total_len as u32
 	}
+	/// Returns the length of the first opcode in the given byte slice.
+	///
+	/// Returns `None` when length disassembling fails, instead of the `0` sentinel returned by [`ld`](#method.ld).
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// assert_eq!(X64::try_ld(b"\x40\x55").map(|len| len.get()), Some(2));
+	/// // push es
+	/// assert_eq!(X64::try_ld(b"\x06"), None);
+	/// ```
+	#[must_use]
+	fn try_ld(bytes: &[u8]) -> Option<num::NonZeroU32> {
+		num::NonZeroU32::new(Self::ld(bytes))
+	}
+	/// Returns the length of the first opcode, limited to `max_len` bytes.
+	///
+	/// Never reads past `max_len` bytes, the return value is `0` if the instruction is longer.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// // add dword ptr ds:[0x2a], eax
+	/// let code = b"\x01\x05\x2A\x00\x00\x00\xCC";
+	/// assert_eq!(X86::ld_max(code, 6), 6);
+	/// assert_eq!(X86::ld_max(code, 5), 0);
+	/// ```
+	fn ld_max(bytes: &[u8], max_len: usize) -> u32 {
+		Self::ld(&bytes[..cmp::min(bytes.len(), max_len)])
+	}
+	/// Returns the length of the first opcode and its operation code bytes.
+	///
+	/// The operation code bytes include the escape bytes but exclude the prefixes and arguments.
+	/// When length disassembling fails the return value is `(0, &[])`.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// // movzx ax, cl
+	/// assert_eq!(X64::ld_op(b"\x66\x0F\xB6\xC1"), (4, &b"\x0F\xB6"[..]));
+	/// // rex push rbp
+	/// assert_eq!(X64::ld_op(b"\x40\x55"), (2, &b"\x55"[..]));
+	/// assert_eq!(X64::ld_op(b"\x66\x0F"), (0, &b""[..]));
+	/// ```
+	fn ld_op(bytes: &[u8]) -> (u32, &[u8]) {
+		let len = Self::inst_len(bytes);
+		(len.total_len as u32, &bytes[len.op_start()..len.arg_start()])
+	}
+	/// Length disassembles successive instructions into the output array.
+	///
+	/// Returns the number of instruction lengths written, stops when the output is full or an instruction fails to decode.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// let mut lens = [0; 4];
+	/// assert_eq!(X64::ld_batch(b"\x40\x55\x48\x83\xEC\xFC\x00\x80", &mut lens), 2);
+	/// assert_eq!(lens, [2, 4, 0, 0]);
+	/// ```
+	fn ld_batch(mut bytes: &[u8], out: &mut [u32]) -> usize {
+		for (i, len) in out.iter_mut().enumerate() {
+			*len = Self::ld(bytes);
+			if *len == 0 {
+				return i;
+			}
+			bytes = &bytes[*len as usize..];
+		}
+		out.len()
+	}
 	/// Returns the number of prefix, opcode, argument and total bytes in the given byte slice.
 	///
 	/// When length disassembling fails, eg. the byte slice does not contain a complete and valid instruction, the return value is `InstLen::EMPTY`.
 	fn inst_len(bytes: &[u8]) -> InstLen;
+	/// Returns the number of prefix, opcode, argument and total bytes in the given byte slice.
+	///
+	/// Distinguishes a byte slice which ends before the instruction does from an invalid instruction.
+	///
+	/// ```
+	/// use lde::{Isa, LenError, X64};
+	/// // mov eax, 0x01010101
+	/// assert_eq!(X64::try_inst_len(b"\xB8\x01\x01\x01\x01").map(|len| len.total_len), Ok(5));
+	/// assert_eq!(X64::try_inst_len(b"\xB8\x01\x01"), Err(LenError::Truncated { have: 3, need: 5 }));
+	/// // push es
+	/// assert_eq!(X64::try_inst_len(b"\x06"), Err(LenError::Invalid));
+	/// ```
+	///
+	/// The default implementation is built on [`inst_len`](#method.inst_len) and reports every failure as [`LenError::Invalid`](enum.LenError.html#variant.Invalid).
+	fn try_inst_len(bytes: &[u8]) -> Result<InstLen, LenError> {
+		let len = Self::inst_len(bytes);
+		if len.total_len == 0 { Err(LenError::Invalid) } else { Ok(len) }
+	}
+	/// Returns the number of prefix, opcode, argument and total bytes with the given default sizes.
+	///
+	/// Decodes regardless of the mode of the instruction set, for code which runs with other defaults.
+	/// A 64-bit address size selects 64-bit mode, where the default operand size of 64-bit is treated as 32-bit as 64-bit operands are selected by REX.W.
+	///
+	/// When length disassembling fails the return value is `InstLen::EMPTY`.
+	///
+	/// ```
+	/// use lde::{Isa, Size, X86};
+	/// // mov eax, **** or mov ax, **
+	/// let code = b"\xB8\x01\x01\x01\x01";
+	/// assert_eq!(X86::inst_len_sized(code, Size::Bits32, Size::Bits32).total_len, 5);
+	/// assert_eq!(X86::inst_len_sized(code, Size::Bits16, Size::Bits32).total_len, 3);
+	/// // mov eax, dword ptr [esp] or mov eax, word ptr [si]
+	/// let code = b"\x8B\x04\x24";
+	/// assert_eq!(X86::inst_len_sized(code, Size::Bits32, Size::Bits32).total_len, 3);
+	/// assert_eq!(X86::inst_len_sized(code, Size::Bits32, Size::Bits16).total_len, 2);
+	/// ```
+	fn inst_len_sized(bytes: &[u8], op_size: Size, addr_size: Size) -> InstLen {
+		let osize = if op_size == Size::Bits16 { 2 } else { 4 };
+		let asize = match addr_size { Size::Bits16 => 2, Size::Bits32 => 4, Size::Bits64 => 8 };
+		x86::decode(bytes, osize, asize).unwrap_or(InstLen::EMPTY)
+	}
 	/// Returns an iterator over the opcodes contained in the byte slice.
 	///
 	/// Given a virtual address to keep track of the instruction pointer.
 	fn iter<'a>(bytes: &'a [u8], va: Self::Va) -> Iter<'a, Self> {
 		Iter { bytes, va }
 	}
+	/// Returns an iterator over the opcodes starting at a virtual address within the byte slice.
+	///
+	/// The virtual address `base_va` is of the start of the byte slice.
+	/// The iterator is empty if `start_va` lies outside of the byte slice.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// let code = b"\x56\x33\xF6\x57\xBF\xA0\x10\x40\x00\x85\xD2\x74\x10\x8B\xF2\x8B\xFA";
+	/// // test edx, edx; je $+0x12; mov esi, edx; mov edi, edx
+	/// let vas: Vec<u32> = X86::iter_at(code, 0x1000, 0x1009).map(|inst| inst.va()).collect();
+	/// assert_eq!(vas, [0x1009, 0x100B, 0x100D, 0x100F]);
+	/// assert_eq!(X86::iter_at(code, 0x1000, 0x0FFF).count(), 0);
+	/// assert_eq!(X86::iter_at(code, 0x1000, 0x1011).count(), 0);
+	/// ```
+	fn iter_at<'a>(bytes: &'a [u8], base_va: Self::Va, start_va: Self::Va) -> Iter<'a, Self> {
+		let offset = start_va.delta(base_va);
+		let bytes = if offset >= 0 && offset as u64 <= bytes.len() as u64 { &bytes[offset as usize..] } else { &[] };
+		Iter { bytes, va: start_va }
+	}
+	/// Decodes the instruction at the given offset in the byte slice.
+	///
+	/// The virtual address `va` is of the start of the byte slice.
+	/// Returns `None` if the offset is out of bounds or the instruction fails to decode.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// let code = b"\x40\x55\x48\x83\xEC*\x00\x80";
+	/// let inst = X64::inst_at(code, 0x1000, 2).unwrap();
+	/// assert_eq!((inst.va(), inst.bytes()), (0x1002, &b"\x48\x83\xEC*"[..]));
+	/// assert!(X64::inst_at(code, 0x1000, 6).is_none());
+	/// assert!(X64::inst_at(code, 0x1000, 9).is_none());
+	/// ```
+	fn inst_at<'a>(bytes: &'a [u8], va: Self::Va, offset: usize) -> Option<Inst<'a, Self>> {
+		let bytes = bytes.get(offset..)?;
+		Self::iter(bytes, va + Self::as_va(offset)).next()
+	}
+	/// Returns an iterator over the byte ranges of the instructions contained in the byte slice.
+	///
+	/// Signature generators can use these instruction boundaries to cut and wildcard their byte patterns.
+	/// The virtual address `va` is of the start of the byte slice, the ranges are offsets into the byte slice so they can index the pattern directly.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// let spans: Vec<_> = X64::pattern_spans(b"\x40\x55\x48\x83\xEC*\x00\x80", 0x1000).collect();
+	/// assert_eq!(spans, [0..2, 2..6]);
+	/// ```
+	fn pattern_spans(bytes: &[u8], va: Self::Va) -> Spans<'_, Self> {
+		Spans { iter: Self::iter(bytes, va).enumerate_offsets() }
+	}
+	/// Finds the first instruction starting a byte pattern.
+	///
+	/// Pattern bytes set to `None` match any byte, the pattern may extend over the following instructions.
+	/// Only instruction boundaries are considered as the start of a match.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// let code = b"\x56\x33\xF6\x57\xBF\xA0\x10\x40\x00\x85\xD2\x74\x10\x8B\xF2\x8B\xFA";
+	/// // mov edi, ????
+	/// let (inst, va) = X86::find_pattern(code, 0x1000, &[Some(0xBF), None, None, None, None]).unwrap();
+	/// assert_eq!((inst.bytes(), va), (&code[4..9], 0x1004));
+	/// // the immediate of the mov edi is not an instruction boundary
+	/// assert!(X86::find_pattern(code, 0x1000, &[Some(0x40), Some(0x00)]).is_none());
+	/// ```
+	fn find_pattern<'a>(bytes: &'a [u8], va: Self::Va, pattern: &[Option<u8>]) -> Option<(Inst<'a, Self>, Self::Va)> {
+		Self::iter(bytes, va).enumerate_offsets()
+			.find(|&(offset, _)| {
+				let tail = &bytes[offset..];
+				tail.len() >= pattern.len() && tail.iter().zip(pattern).all(|(&byte, &pat)| pat.is_none() || pat == Some(byte))
+			})
+			.map(|(_, inst)| (inst, inst.va()))
+	}
+	/// Returns the start of the instruction containing the given offset.
+	///
+	/// Instructions cannot be length disassembled backwards, instead the byte slice is length disassembled from the start.
+	/// The result is only meaningful if the byte slice starts at an instruction boundary, eg. the start of a function.
+	///
+	/// Returns `None` if an instruction fails to decode before reaching the offset.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// let code = b"\x40\x55\x48\x83\xEC*\x00\x80";
+	/// assert_eq!(X64::align_back(code, 4), Some(2));
+	/// assert_eq!(X64::align_back(code, 6), Some(6));
+	/// assert_eq!(X64::align_back(code, 9), None);
+	/// ```
+	fn align_back(bytes: &[u8], offset: usize) -> Option<usize> {
+		let mut start = 0;
+		while start < offset {
+			let len = Self::inst_len(&bytes[start..]).total_len as usize;
+			if len == 0 {
+				return None;
+			}
+			if start + len > offset {
+				break;
+			}
+			start += len;
+		}
+		Some(start)
+	}
+	/// Collects the byte ranges and virtual addresses of the instructions contained in the byte slice.
+	///
+	/// Stops at the first instruction which fails to decode.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// let insts = X64::disassemble(b"\x40\x55\x48\x83\xEC*\x00\x80", 0x1000);
+	/// assert_eq!(insts, [(0..2, 0x1000), (2..6, 0x1002)]);
+	/// ```
+	#[cfg(feature = "alloc")]
+	fn disassemble(bytes: &[u8], va: Self::Va) -> alloc::vec::Vec<(ops::Range<usize>, Self::Va)> {
+		Self::iter(bytes, va).enumerate_offsets().map(|(offset, inst)| (offset..offset + inst.len(), inst.va())).collect()
+	}
+	/// Returns the virtual address after stepping over `n` instructions.
+	///
+	/// Returns `None` if any of the `n` instructions fails to decode.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// let code = b"\x40\x55\x48\x83\xEC*\x00\x80";
+	/// assert_eq!(X64::advance(code, 0x1000, 2), Some(0x1006));
+	/// assert_eq!(X64::advance(code, 0x1000, 3), None);
+	/// ```
+	fn advance(bytes: &[u8], va: Self::Va, n: usize) -> Option<Self::Va> {
+		let mut iter = Self::iter(bytes, va);
+		for _ in 0..n {
+			iter.next()?;
+		}
+		Some(iter.va)
+	}
+	/// Length disassembles and classifies the first instruction in the given byte slice.
+	///
+	/// Returns `None` if the byte slice does not start with a complete and valid instruction.
+	///
+	/// The classification is the same as [`Inst::flow`](struct.Inst.html#method.flow) and [`Inst::accesses_memory`](struct.Inst.html#method.accesses_memory)
+	/// for the instruction at the virtual address `va`.
+	/// This is not a single decoding pass: the instruction is length disassembled first and its opcode bytes are inspected again to classify it.
+	///
+	/// ```
+	/// use lde::{Flow, Isa, X64};
+	/// // call qword ptr [rip+****]
+	/// let info = X64::probe(b"\xFF\x15****", 0x1000).unwrap();
+	/// assert_eq!(info.len.total_len, 6);
+	/// assert_eq!(info.flow, Flow::Call);
+	/// assert!(info.mem);
+	/// ```
+	fn probe(bytes: &[u8], va: Self::Va) -> Option<InstInfo> {
+		let len = Self::inst_len(bytes);
+		if len.total_len == 0 {
+			return None;
+		}
+		let inst = Inst::<Self>::new(&bytes[..len.total_len as usize], va, len);
+		Some(InstInfo { len, flow: inst.flow(), mem: inst.accesses_memory() })
+	}
+	/// Returns the instructions which must be preserved to place a hook of `required` bytes.
+	///
+	/// The preserved length is the first instruction boundary at or past `required` bytes.
+	///
+	/// ```
+	/// use lde::{Isa, NotEnoughBytes, X64};
+	/// let code = b"\x40\x55\x48\x8D\x05****\x00\x80";
+	/// // push rbp; lea rax, [rip+****]
+	/// let info = X64::hook_prologue(code, 0x1000, 5).unwrap();
+	/// assert_eq!((info.len, info.count, info.needs_relocation), (9, 2, true));
+	/// // push rbp
+	/// let info = X64::hook_prologue(code, 0x1000, 1).unwrap();
+	/// assert_eq!((info.len, info.count, info.needs_relocation), (2, 1, false));
+	/// // an absolute jmp does not fit
+	/// assert_eq!(X64::hook_prologue(code, 0x1000, 14), Err(NotEnoughBytes));
+	/// ```
+	fn hook_prologue(bytes: &[u8], va: Self::Va, required: usize) -> Result<PrologueInfo, NotEnoughBytes> {
+		let mut info = PrologueInfo { len: 0, count: 0, needs_relocation: false };
+		let mut iter = Self::iter(bytes, va);
+		while info.len < required {
+			let inst = iter.next().ok_or(NotEnoughBytes)?;
+			info.len += inst.len();
+			info.count += 1;
+			info.needs_relocation |= inst.rip_target().is_some() || inst.flow() != Flow::Next;
+		}
+		Ok(info)
+	}
+	/// Returns whether the byte slice consists of whole instructions only.
+	///
+	/// Length disassembles the byte slice front to back and stops at the first instruction which fails to decode.
+	/// An empty byte slice trivially decodes exactly.
+	///
+	/// ```
+	/// use lde::{Isa, X64};
+	/// assert!(X64::decodes_exactly(b"\x40\x55\x48\x83\xEC*"));
+	/// assert!(!X64::decodes_exactly(b"\x40\x55\x48\x83\xEC"));
+	/// ```
+	fn decodes_exactly(mut bytes: &[u8]) -> bool {
+		while !bytes.is_empty() {
+			let len = Self::inst_len(bytes).total_len as usize;
+			if len == 0 {
+				return false;
+			}
+			bytes = &bytes[len..];
+		}
+		true
+	}
+	/// Counts the instructions in the byte slice.
+	///
+	/// Stops at the first instruction which fails to decode, any trailing bytes are not counted.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// let code = b"\x56\x33\xF6\x57\xBF\xA0\x10\x40\x00\x85\xD2\x74\x10\x8B\xF2\x8B\xFA";
+	/// assert_eq!(X86::count(code), 8);
+	/// assert_eq!(X86::count(&code[..16]), 7);
+	/// ```
+	fn count(mut bytes: &[u8]) -> usize {
+		let mut count = 0;
+		loop {
+			let len = Self::inst_len(bytes).total_len as usize;
+			if len == 0 {
+				return count;
+			}
+			bytes = &bytes[len..];
+			count += 1;
+		}
+	}
+	/// Overwrites the bytes with NOP instructions.
+	///
+	/// Uses the multi-byte NOP encodings to cover the bytes with as few instructions as possible.
+	/// Useful to pad out the remains of instructions overwritten by a hook.
+	///
+	/// ```
+	/// use lde::{Isa, X86};
+	/// // mov eax, 0x01010101
+	/// let mut code = *b"\xB8\x01\x01\x01\x01";
+	/// X86::fill_nop(&mut code);
+	/// // nop dword ptr [eax+eax+0]
+	/// assert_eq!(&code, b"\x0F\x1F\x44\x00\x00");
+	/// assert_eq!(X86::ld(&code), 5);
+	/// ```
+	fn fill_nop(bytes: &mut [u8]) {
+		// The longer encodings need a SIB byte which is not available with 16-bit addressing
+		let max = if Self::ADDR_SIZE == Size::Bits16 { 4 } else { NOPS.len() };
+		fill_nop(bytes, max);
+	}
 	#[doc(hidden)]
 	fn as_va(len: usize) -> Self::Va;
 }
@@ -213,7 +843,11 @@ pub trait Isa: Sized {
 pub struct X86;
 impl Isa for X86 {
 	type Va = u32;
+	const ADDR_SIZE: Size = Size::Bits32;
 	fn inst_len(bytes: &[u8]) -> InstLen {
+		x86::inst_len(bytes).unwrap_or(InstLen::EMPTY)
+	}
+	fn try_inst_len(bytes: &[u8]) -> Result<InstLen, LenError> {
 		x86::inst_len(bytes)
 	}
 	#[doc(hidden)]
@@ -222,11 +856,41 @@ impl Isa for X86 {
 	}
 }
 
+/// Length disassembler for the 16-bit mode of the `x86` instruction set architecture.
+///
+/// The default operand and address size is 16 bits, the override prefixes select 32 bits.
+/// Virtual addresses are the flattened `segment * 16 + offset` linear addresses.
+///
+/// ```
+/// use lde::{Isa, X16};
+/// // mov ax, word ptr [bp+0x1234]
+/// assert_eq!(X16::ld(b"\x8B\x86\x34\x12"), 4);
+/// ```
+pub struct X16;
+impl Isa for X16 {
+	type Va = u32;
+	const ADDR_SIZE: Size = Size::Bits16;
+	fn inst_len(bytes: &[u8]) -> InstLen {
+		x86::inst_len16(bytes).unwrap_or(InstLen::EMPTY)
+	}
+	fn try_inst_len(bytes: &[u8]) -> Result<InstLen, LenError> {
+		x86::inst_len16(bytes)
+	}
+	#[doc(hidden)]
+	fn as_va(len: usize) -> u32 {
+		len as u32
+	}
+}
+
 /// Length disassembler for the `x86_64` instruction set architecture.
 pub struct X64;
 impl Isa for X64 {
 	type Va = u64;
+	const ADDR_SIZE: Size = Size::Bits64;
 	fn inst_len(bytes: &[u8]) -> InstLen {
+		x64::inst_len(bytes).unwrap_or(InstLen::EMPTY)
+	}
+	fn try_inst_len(bytes: &[u8]) -> Result<InstLen, LenError> {
 		x64::inst_len(bytes)
 	}
 	#[doc(hidden)]
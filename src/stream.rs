@@ -0,0 +1,185 @@
+use core::{cmp, marker};
+use *;
+
+/// Length disassembler for bytes arriving incrementally.
+///
+/// Buffers up to a single maximum length instruction.
+///
+/// ```
+/// use lde::{Isa, StreamDecoder, X64};
+/// let mut stream = StreamDecoder::<X64>::new();
+/// // push rbp; sub rsp, 0x2a
+/// assert_eq!(stream.push(b"\x40\x55\x48"), 3);
+/// assert_eq!(stream.pull().map(|len| len.total_len), Some(2));
+/// assert_eq!(stream.last(), b"\x40\x55");
+/// // needs more bytes
+/// assert_eq!(stream.pull(), None);
+/// assert_eq!(stream.push(b"\x83\xEC*"), 3);
+/// assert_eq!(stream.pull().map(|len| len.total_len), Some(4));
+/// assert_eq!(stream.last(), b"\x48\x83\xEC*");
+/// ```
+pub struct StreamDecoder<X: Isa> {
+	buf: [u8; 15],
+	len: u8,
+	last: u8,
+	isa: marker::PhantomData<X>,
+}
+
+impl<X: Isa> Clone for StreamDecoder<X> {
+	fn clone(&self) -> Self {
+		StreamDecoder {
+			buf: self.buf,
+			len: self.len,
+			last: self.last,
+			isa: marker::PhantomData,
+		}
+	}
+}
+
+impl<X: Isa> Default for StreamDecoder<X> {
+	fn default() -> Self {
+		StreamDecoder::new()
+	}
+}
+
+impl<X: Isa> StreamDecoder<X> {
+	/// Creates an empty stream decoder.
+	pub fn new() -> StreamDecoder<X> {
+		StreamDecoder { buf: [0; 15], len: 0, last: 0, isa: marker::PhantomData }
+	}
+	/// Buffers bytes from the input.
+	///
+	/// Returns the number of bytes buffered, which is less than the input once the buffer is full.
+	/// Pull the buffered instructions to make room for more bytes.
+	pub fn push(&mut self, bytes: &[u8]) -> usize {
+		self.compact();
+		let start = self.len as usize;
+		let n = cmp::min(bytes.len(), self.buf.len() - start);
+		self.buf[start..start + n].copy_from_slice(&bytes[..n]);
+		self.len += n as u8;
+		n
+	}
+	/// Length disassembles the next buffered instruction.
+	///
+	/// Returns `None` if more bytes are needed to complete the instruction, or if the buffered bytes are invalid.
+	pub fn pull(&mut self) -> Option<InstLen> {
+		self.try_pull().ok()
+	}
+	/// Length disassembles the next buffered instruction.
+	///
+	/// The error distinguishes a truncated instruction, which needs more bytes, from invalid bytes.
+	/// The bytes stay buffered on error.
+	pub fn try_pull(&mut self) -> Result<InstLen, LenError> {
+		self.compact();
+		let len = X::try_inst_len(&self.buf[..self.len as usize])?;
+		self.last = len.total_len;
+		Ok(len)
+	}
+	/// Skips a byte if the buffered bytes start with an invalid instruction.
+	///
+	/// Returns whether pulling may continue, which is not the case when the buffered bytes are empty or truncated.
+	/// Analogous to [`Iter::resync`](struct.Iter.html#method.resync), recovers the stream after [`LenError::Invalid`](enum.LenError.html#variant.Invalid).
+	///
+	/// ```
+	/// use lde::{LenError, StreamDecoder, X64};
+	/// let mut stream = StreamDecoder::<X64>::new();
+	/// // push es is invalid in 64-bit mode; ret
+	/// stream.push(b"\x06\xC3");
+	/// assert_eq!(stream.try_pull(), Err(LenError::Invalid));
+	/// assert!(stream.resync());
+	/// assert_eq!(stream.pull().map(|len| len.total_len), Some(1));
+	/// assert_eq!(stream.last(), b"\xC3");
+	/// ```
+	pub fn resync(&mut self) -> bool {
+		self.compact();
+		match X::try_inst_len(&self.buf[..self.len as usize]) {
+			Ok(_) => true,
+			Err(LenError::Invalid) => {
+				self.skip(1);
+				self.len != 0
+			},
+			Err(LenError::Truncated { .. }) => false,
+		}
+	}
+	/// Drops up to `n` buffered bytes which are not yet pulled.
+	///
+	/// Returns the number of bytes dropped.
+	pub fn skip(&mut self, n: usize) -> usize {
+		self.compact();
+		let n = cmp::min(n, self.len as usize);
+		self.buf.copy_within(n..self.len as usize, 0);
+		self.len -= n as u8;
+		n
+	}
+	/// Gets the bytes of the last pulled instruction.
+	///
+	/// Empty if no instruction was pulled since the last push.
+	pub fn last(&self) -> &[u8] {
+		&self.buf[..self.last as usize]
+	}
+	/// Gets the buffered bytes which are not yet pulled.
+	pub fn buffered(&self) -> &[u8] {
+		&self.buf[self.last as usize..self.len as usize]
+	}
+	// Drops the last pulled instruction from the buffer.
+	fn compact(&mut self) {
+		let last = self.last as usize;
+		self.buf.copy_within(last..self.len as usize, 0);
+		self.len -= self.last;
+		self.last = 0;
+	}
+}
+
+//----------------------------------------------------------------
+
+#[test]
+fn split() {
+	// push esi; xor esi, esi; push edi; mov edi, 0x4010a0; test edx, edx; je $+0x12
+	let code = b"\x56\x33\xF6\x57\xBF\xA0\x10\x40\x00\x85\xD2\x74\x10";
+	let mut stream = StreamDecoder::<X86>::new();
+	let mut lens = [0; 6];
+	let mut n = 0;
+	for chunk in [&code[..2], &code[2..7], &code[7..]].iter() {
+		assert_eq!(stream.push(chunk), chunk.len());
+		while let Some(len) = stream.pull() {
+			lens[n] = len.total_len;
+			n += 1;
+		}
+	}
+	assert_eq!(lens, [1, 2, 1, 5, 2, 2]);
+	assert!(stream.buffered().is_empty());
+	// pop es is invalid in 64-bit mode
+	let mut stream = StreamDecoder::<X64>::new();
+	stream.push(b"\x07");
+	assert_eq!(stream.try_pull(), Err(LenError::Invalid));
+	assert_eq!(stream.buffered(), b"\x07");
+}
+
+#[test]
+fn resync() {
+	// pop es; push es; push rbp; sub rsp, 0x2a, all split over two pushes
+	let mut stream = StreamDecoder::<X64>::new();
+	stream.push(b"\x07\x06\x55\x48");
+	assert_eq!(stream.try_pull(), Err(LenError::Invalid));
+	// stays wedged until resynced
+	assert_eq!(stream.try_pull(), Err(LenError::Invalid));
+	assert!(stream.resync());
+	assert!(stream.resync());
+	assert_eq!(stream.buffered(), b"\x55\x48");
+	assert_eq!(stream.pull().map(|len| len.total_len), Some(1));
+	// a truncated instruction needs more bytes
+	assert!(!stream.resync());
+	assert_eq!(stream.buffered(), b"\x48");
+	stream.push(b"\x83\xEC*");
+	assert_eq!(stream.pull().map(|len| len.total_len), Some(4));
+	assert!(!stream.resync());
+	// invalid trailing byte empties the buffer
+	stream.push(b"\x06");
+	assert!(!stream.resync());
+	assert!(stream.buffered().is_empty());
+	// skip drops buffered bytes
+	stream.push(b"\x06\x06\xC3");
+	assert_eq!(stream.skip(2), 2);
+	assert_eq!(stream.pull().map(|len| len.total_len), Some(1));
+	assert_eq!(stream.skip(5), 0);
+}
@@ -7,10 +7,12 @@ References:
 May contain errors...
 */
 
-use contains::Contains;
-use InstLen;
+use x86;
+use {InstLen, LenError};
 
-static TABLE_PREFIX: [u32; 8] = [
+// 64-bit mode shares the decoder with the other modes, these tables hold the differences.
+
+pub(crate) static TABLE_PREFIX: [u32; 8] = [
 	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 0
 	0b_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0,// 2
@@ -22,7 +24,7 @@ static TABLE_PREFIX: [u32; 8] = [
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_0_1_1_0_0_0_0_0_0_0_0_0_0_0_0,// E
 ];
 //---- One-byte opcodes ----
-static TABLE_INVALID_A: [u32; 8] = [
+pub(crate) static TABLE_INVALID_A: [u32; 8] = [
 	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
 	0b_0_0_0_0_0_0_1_1_0_0_0_0_0_0_1_1_0_0_0_0_0_0_1_1_0_0_0_0_0_0_1_1,// 0
 	0b_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1,// 2
@@ -30,240 +32,58 @@ static TABLE_INVALID_A: [u32; 8] = [
 	0b_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 6
 	0b_0_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_0_0_0_0_0,// 8
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// A
-	0b_0_0_0_0_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_0_0_0_0_0_0_0_0_0,// C
+	0b_0_0_0_0_1_1_0_0_0_0_0_0_0_0_1_0_0_0_0_0_1_1_1_0_0_0_0_0_0_0_0_0,// C
 	0b_0_0_0_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// E
 ];
-static TABLE_MODRM_A: [u32; 8] = [
-	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
-	0b_1_1_1_1_0_0_0_0_1_1_1_1_0_0_0_0_1_1_1_1_0_0_0_0_1_1_1_1_0_0_0_0,// 0
-	0b_1_1_1_1_0_0_0_0_1_1_1_1_0_0_0_0_1_1_1_1_0_0_0_0_1_1_1_1_0_0_0_0,// 2
-	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 4
-	0b_0_0_1_1_0_0_0_0_0_1_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 6
-	0b_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 8
-	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// A
-	0b_1_1_0_0_1_1_1_1_0_0_0_0_0_0_0_0_1_1_1_1_0_0_0_0_1_1_1_1_1_1_1_1,// C
-	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_1_0_0_0_0_0_0_1_1,// E
-];
-static TABLE_IMM8_A: [u32; 8] = [
+// Opcodes which are always a single byte: not a prefix, without arguments and valid
+pub(crate) static TABLE_SINGLE_A: [u32; 8] = [
 	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
-	0b_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0,// 0
-	0b_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0,// 2
-	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 4
-	0b_0_0_0_0_0_0_0_0_0_0_1_1_0_0_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// 6
-	0b_1_0_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 8
-	0b_0_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1_0_0_0_0_0_0_0_0,// A
-	0b_1_1_0_0_0_0_1_0_1_0_0_0_0_1_0_0_0_0_0_0_1_1_0_0_0_0_0_0_0_0_0_0,// C
-	0b_1_1_1_1_1_1_1_1_0_0_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// E
-];
-static TABLE_IMM_A: [u32; 8] = [
-	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
-	0b_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0,// 0
-	0b_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0,// 2
-	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 4
-	0b_0_0_0_0_0_0_0_0_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 6
-	0b_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_0_0_0_0_0,// 8
-	0b_0_0_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1,// A
-	0b_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// C
-	0b_0_0_0_0_0_0_0_0_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// E
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 0
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 2
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// 4
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 6
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1_1_1_0_0_1_1_1_1,// 8
+	0b_0_0_0_0_1_1_1_1_0_0_1_1_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// A
+	0b_0_0_0_1_0_0_0_0_0_1_0_1_1_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_0,// C
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_0_1_0_0_1_1_0_0_1_1_1_1_1_1_0_0,// E
 ];
 //---- Two-byte opcodes ----
-static TABLE_MODRM_B: [u32; 8] = [
+pub(crate) static TABLE_INVALID_B: [u32; 8] = [
 	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
-	0b_1_1_1_1_0_0_0_0_0_0_0_0_0_1_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// 0
-	0b_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 2
-	0b_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// 4
-	0b_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_0_0_0_1_1_1_0_1_1_1_1_1_1_1_1,// 6
-	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// 8
-	0b_0_0_0_1_1_1_0_0_0_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_0_1_1_1_1_1_1,// A
-	0b_1_1_1_1_1_1_1_1_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// C
-	0b_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// E
-];
-static TABLE_INVALID_B: [u32; 8] = [
-	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
-	0b_0_0_0_0_1_0_0_0_0_0_1_0_1_0_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 0
+	0b_0_0_0_0_1_0_0_0_0_0_1_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 0
 	0b_0_0_0_0_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_0_1_1_1_1_1_1_1_1,// 2
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 4
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_1_0_0_0_0,// 6
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 8
-	0b_0_0_0_0_0_0_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// A
-	0b_0_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// C
-	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1,// E
-];
-//---- Three-byte opcodes 38 ----
-static TABLE_INVALID_C: [u32; 2] = [
-	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
-	0b_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_0_1_1_1_0_0_1_0_1_1_1_1_0_0_0_1,// 0
-	0b_0_0_0_0_0_0_1_1_0_0_0_0_1_1_1_1_0_0_0_0_0_0_1_0_0_0_0_0_0_0_0_0,// 2
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// A
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// C
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// E
 ];
-//---- Three-byte opcodes 3A ----
-
-pub fn inst_len(opcode: &[u8]) -> InstLen {
-	let modrm;
-	let mut op: u8;
-	let (mut ddef, mut mdef) = (4u32, 8u32);
-	let (mut dsize, mut msize) = (0u32, 0u32);
-	let mut rex_w = false;
-	let mut it = opcode.iter();
-
-	// Prefixes
-	let mut prefix_len = 0;
-	loop {
-		op = match it.next() {
-			Some(&op) => op,
-			None => return InstLen::EMPTY,
-		};
-		if TABLE_PREFIX.has(op) {
-			prefix_len += 1;
-			// Operand-size override prefix
-			if op == 0x66 { ddef = 2u32; }
-			// Address-size override prefix
-			else if op == 0x67 { mdef = 4u32; }
-			// REX prefixes with 0x8 set (W)
-			else if (0x48..0x50).has(op) { rex_w = true; }
-		}
-		else {
-			break;
-		}
-	}
-
-	let mut op_len = 1;
-	if op == 0x0F {
-		op = match it.next() {
-			Some(&op) => op,
-			None => return InstLen::EMPTY,
-		};
-		op_len += 1;
-		// Three-byte opcodes (C)
-		if op == 0x38 {
-			op = match it.next() {
-				Some(&op) => op,
-				None => return InstLen::EMPTY,
-			};
-			op_len += 1;
-			// Invalid opcodes
-			if if op < 0x40 { TABLE_INVALID_C.has(op) } else { !((0x40..0x42).has(op) || (0x80..0x82).has(op) || (0xF0..0xF2).has(op)) } { return InstLen::EMPTY; };
-			modrm = true;
-		}
-		// Three-byte opcodes (D)
-		else if op == 0x3A {
-			op = match it.next() {
-				Some(&op) => op,
-				None => return InstLen::EMPTY,
-			};
-			op_len += 1;
-			// Invalid opcodes
-			if !((0x08..0x10).has(op) || (0x14..0x18).has(op) || (0x20..0x23).has(op) || (0x40..0x43).has(op) || (0x60..0x64).has(op)) { return InstLen::EMPTY; };
-			modrm = true;
-			dsize += 1;
-		}
-		// Two-byte opcodes (B)
-		else {
-			// Invalid opcodes
-			if TABLE_INVALID_B.has(op) {
-				return InstLen::EMPTY;
-			}
-			modrm = TABLE_MODRM_B.has(op);
-			// Check for imm8
-			if (0x70..0x74).has(op) || op == 0xA4 || op == 0xAC || op == 0xBA || op == 0xC2 || (0xC4..0xC7).has(op) {
-				dsize += 1;
-			}
-			// Check for imm16
-			if (op & 0xF0) == 0x80 {
-				dsize += ddef;
-			}
-		}
-	}
-	// One-byte opcodes (A)
-	else {
-		// Reject invalid opcodes
-		if TABLE_INVALID_A.has(op) {
-			return InstLen::EMPTY;
-		}
-		modrm = TABLE_MODRM_A.has(op);
-		// Check `test` opcode with immediate
-		if (op == 0xF6 || op == 0xF7) && (if let Some(&op) = it.clone().next() { op } else { return InstLen::EMPTY; } & 0x38) == 0 {
-			dsize += if (op & 1) != 0 { ddef } else { 1 }
-		}
-		// Check for imm8
-		if TABLE_IMM8_A.has(op) {
-			dsize += 1;
-		}
-		// Check for imm16: RETN Iw, ENTER eBP Iw Ib, RETF Iw
-		if op == 0xC2 || op == 0xC8 || op == 0xCA {
-			dsize += 2;
-		}
-		// Check for immediate
-		if TABLE_IMM_A.has(op) {
-			// `mov reg, imm` uses 64-bit immediate if REX.W is set
-			if (0xb8..0xc0).has(op) && rex_w {
-				dsize += 8;
-			} else {
-				dsize += ddef;
-			}
-		}
-		// Special snowflake `movabs`
-		if (op & 0xFC) == 0xA0 {
-			msize += mdef;
-		}
-	}
-
-	// Mod R/M
-	if modrm {
-		op = match it.next() {
-			Some(&op) => op,
-			None => return InstLen::EMPTY,
-		};
-		let mode = op & 0xC0;
-		let rm = op & 0b111;
-		if mode != 0xC0 {
-			if rm == 0b100 {
-				// Scaled Index Byte
-				op = match it.next() {
-					Some(&op) => op,
-					None => return InstLen::EMPTY,
-				};
-				if mode == 0x00 {
-					if (op & 0b111) == 0b101 {
-						msize += 4;
-					}
-				}
-			}
-			if mode == 0x00 {
-				if rm == 0b101 {
-					msize += 4;
-				}
-			}
-			else if mode == 0x40 {
-				msize += 1;
-			}
-			else if mode == 0x80 {
-				msize += 4;
-			}
-		}
-	}
-
-	// Get total length and bounds check
-	let total_len = ((it.as_slice().as_ptr() as usize).wrapping_sub(opcode.as_ptr() as usize)) as u32;
-	let total_len = total_len.wrapping_add(dsize + msize) as u8;
 
-	let arg_len = total_len - prefix_len - op_len;
-	if total_len as usize <= opcode.len() {
-		InstLen { total_len, op_len, arg_len, prefix_len }
-	}
-	else {
-		InstLen::EMPTY
-	}
+pub fn inst_len(opcode: &[u8]) -> Result<InstLen, LenError> {
+	x86::decode(opcode, 4, 8)
 }
 
 //----------------------------------------------------------------
 
 #[cfg(test)]
 fn lde_int(bytes: &[u8]) -> u32 {
-	inst_len(bytes).total_len as u32
+	inst_len(bytes).map_or(0, |len| len.total_len as u32)
 }
 
 #[test]
 fn units() {
+	// test al, *
+	assert_eq!(lde_int(b"\xF6\xC0*"), 3);
+	// test al, * (reg field 1)
+	assert_eq!(lde_int(b"\xF6\xC8*"), 3);
+	// neg eax
+	assert_eq!(lde_int(b"\xF7\xD8"), 2);
+	// test DWORD PTR [rax], ****
+	assert_eq!(lde_int(b"\xF7\x00****"), 6);
+	// fwait
+	assert_eq!(lde_int(b"\x9B"), 1);
+	assert_eq!(lde_int(b"\x9B\x90"), 1);
 	// sub rsp, *
 	assert_eq!(lde_int(b"\x48\x83\xEC*"), 4);
 	// lea rcx, [rip+****]
@@ -274,6 +94,21 @@ fn units() {
 	assert_eq!(lde_int(b"\x48\xA3********"), 10);
 	// addr32 mov ds:****, eax
 	assert_eq!(lde_int(b"\x67\x48\xA3****"), 7);
+	// movabs rax, ds:********, movabs eax, ds:********, movabs al, ds:********
+	assert_eq!(lde_int(b"\x48\xA1********"), 10);
+	assert_eq!(lde_int(b"\xA1********"), 9);
+	assert_eq!(lde_int(b"\xA0********"), 9);
+	// addr32 mov eax, ds:****, the operand-size override prefix does not affect the offset
+	assert_eq!(lde_int(b"\x67\xA1****"), 6);
+	assert_eq!(lde_int(b"\x66\xA1********"), 10);
+	// mov eax, [eax], 67 selects 32-bit addressing in 64-bit mode
+	assert_eq!(lde_int(b"\x67\x8B\x00"), 3);
+	// mov eax, [eax+*]
+	assert_eq!(lde_int(b"\x67\x8B\x40*"), 4);
+	// mov eax, [eip+****]
+	assert_eq!(lde_int(b"\x67\x8B\x05****"), 7);
+	// mov eax, ds:****
+	assert_eq!(lde_int(b"\x67\x8B\x04\x25****"), 8);
 	// mov qword ptr [rbp+****], ****
 	assert_eq!(lde_int(b"\x48\xC7\x85\xA8\x00\x00\x00\x0C\x00\x00\x00"), 11);
 	// mov word ptr [rsp+*], **
@@ -282,10 +117,222 @@ fn units() {
 	assert_eq!(lde_int(b"\x0F\x1F\x40\x00"), 4);
 	// nop dword ptr [rax+****]
 	assert_eq!(lde_int(b"\x66\x0F\x0D\x80****"), 8);
+	// prefetch byte ptr [eax]
+	assert_eq!(lde_int(b"\x0F\x0D\x00"), 3);
+	// prefetchw byte ptr [eax]
+	assert_eq!(lde_int(b"\x0F\x0D\x08"), 3);
+	// prefetchw byte ptr [rax+*]
+	assert_eq!(lde_int(b"\x0F\x0D\x48*"), 4);
+	// nop dword ptr [rax+rax*1+0x0]
+	assert_eq!(lde_int(b"\x0F\x1F\x84\x00\x00\x00\x00\x00"), 8);
+	// prefetchnta byte ptr [rax], byte ptr [rsp], byte ptr [rax+****]
+	assert_eq!(lde_int(b"\x0F\x18\x00"), 3);
+	assert_eq!(lde_int(b"\x0F\x18\x04\x24"), 4);
+	assert_eq!(lde_int(b"\x0F\x18\x80****"), 7);
 	// weird nop
 	assert_eq!(lde_int(b"\x66\x66\x0f\x1f\x84\x00\x00\x00\x00\x00"), 10);
 	// rep movsb
 	assert_eq!(lde_int(b"\xF3\xA4"), 2);
 	// mov r15, ********
 	assert_eq!(lde_int(b"\x49\xBF********"), 10);
+	// movabs rax, ********, mov eax, ****, mov ax, **
+	assert_eq!(lde_int(b"\x48\xB8********"), 10);
+	assert_eq!(lde_int(b"\xB8****"), 5);
+	assert_eq!(lde_int(b"\x66\xB8**"), 4);
+	// REX.W takes precedence over the operand-size override prefix
+	assert_eq!(lde_int(b"\x66\x48\xB8********"), 11);
+	assert_eq!(lde_int(b"\x66\x48\x05****"), 7);
+	assert_eq!(lde_int(b"\x66\x48\xC7\xC0****"), 8);
+	// REX is ignored unless it directly precedes the opcode: cs mov edi, ****
+	assert_eq!(lde_int(b"\x48\x2E\xBF****"), 7);
+	// push ****
+	assert_eq!(lde_int(b"\x68****"), 5);
+	// push **
+	assert_eq!(lde_int(b"\x66\x68**"), 4);
+	// push *
+	assert_eq!(lde_int(b"\x6A*"), 2);
+	assert_eq!(lde_int(b"\x66\x6A*"), 3);
+	// endbr32, endbr64
+	assert_eq!(lde_int(b"\xF3\x0F\x1E\xFB"), 4);
+	assert_eq!(lde_int(b"\xF3\x0F\x1E\xFA"), 4);
+	// rdsspd eax
+	assert_eq!(lde_int(b"\xF3\x0F\x1E\xC8"), 4);
+	// rdsspq rax
+	assert_eq!(lde_int(b"\xF3\x48\x0F\x1E\xC8"), 5);
+	// nop (reserved) byte ptr [eax], byte ptr [eax+****]
+	assert_eq!(lde_int(b"\x0F\x1E\x00"), 3);
+	assert_eq!(lde_int(b"\x0F\x1E\x80****"), 7);
+	// syscall, sysret, sysenter, sysexit
+	assert_eq!(lde_int(b"\x0F\x05"), 2);
+	assert_eq!(lde_int(b"\x0F\x07"), 2);
+	assert_eq!(lde_int(b"\x0F\x34"), 2);
+	assert_eq!(lde_int(b"\x0F\x35"), 2);
+	// popcnt eax, ecx
+	assert_eq!(lde_int(b"\xF3\x0F\xB8\xC1"), 4);
+	// popcnt eax, dword ptr [ebp+*]
+	assert_eq!(lde_int(b"\xF3\x0F\xB8\x45*"), 5);
+	// popcnt rax, rcx
+	assert_eq!(lde_int(b"\xF3\x48\x0F\xB8\xC1"), 5);
+	// tzcnt eax, ecx
+	assert_eq!(lde_int(b"\xF3\x0F\xBC\xC1"), 4);
+	// lzcnt eax, ecx
+	assert_eq!(lde_int(b"\xF3\x0F\xBD\xC1"), 4);
+	// shl eax, 3
+	assert_eq!(lde_int(b"\xC1\xE0\x03"), 3);
+	// ret 8
+	assert_eq!(lde_int(b"\xC2\x08\x00"), 3);
+	// int3
+	assert_eq!(lde_int(b"\xCC"), 1);
+	// call qword ptr [rax]
+	assert_eq!(lde_int(b"\xFF\x10"), 2);
+	// bnd ret, bnd jmp ****
+	assert_eq!(lde_int(b"\xF2\xC3"), 2);
+	assert_eq!(lde_int(b"\xF2\xE9****"), 6);
+	// bswap r8, ud0 eax, dword ptr [rax]
+	assert_eq!(lde_int(b"\x49\x0F\xC8"), 3);
+	assert_eq!(lde_int(b"\x0F\xFF\x00"), 3);
+	// cmovne rax, rcx; cmovl eax, dword ptr [rbp+*]
+	assert_eq!(lde_int(b"\x48\x0F\x45\xC1"), 4);
+	assert_eq!(lde_int(b"\x0F\x4C\x45*"), 4);
+}
+
+#[cfg(not(feature = "minimal"))]
+#[test]
+fn simd() {
+	// fclex
+	assert_eq!(lde_int(b"\x9B\xDB\xE2"), 3);
+	// pfadd mm0, mm1
+	assert_eq!(lde_int(b"\x0F\x0F\xC1\x9E"), 4);
+	// pfmul mm0, QWORD PTR [rax+*]
+	assert_eq!(lde_int(b"\x0F\x0F\x40*\xB4"), 5);
+	// vpxord zmm0, zmm0, zmm0
+	assert_eq!(lde_int(b"\x62\xF1\x7D\x48\xEF\xC0"), 6);
+	// vpxorq zmm0, zmm0, zmmword ptr [rax+0x40], compressed disp8
+	assert_eq!(lde_int(b"\x62\xF1\xFD\x48\xEF\x40\x01"), 7);
+	// vaddph zmm0, zmm0, zmm1
+	assert_eq!(lde_int(b"\x62\xF5\x7C\x48\x58\xC1"), 6);
+	// vmovaps xmm0, xmm1
+	assert_eq!(lde_int(b"\xC5\xF8\x28\xC1"), 4);
+	// vmovdqu ymm0, ymmword ptr [rax+*]
+	assert_eq!(lde_int(b"\xC5\xFE\x6F\x40*"), 5);
+	// vzeroupper
+	assert_eq!(lde_int(b"\xC5\xF8\x77"), 3);
+	// vpshufb xmm0, xmm1, xmm2
+	assert_eq!(lde_int(b"\xC4\xE2\x71\x00\xC2"), 5);
+	// vpermq ymm0, ymm1, *
+	assert_eq!(lde_int(b"\xC4\xE3\xFD\x00\xC1*"), 6);
+	// vpshufd ymm0, ymm1, *
+	assert_eq!(lde_int(b"\xC4\xE1\x7D\x70\xC1*"), 6);
+	// vaddps zmm0, zmm1, zmm2
+	assert_eq!(lde_int(b"\x62\xF1\x74\x48\x58\xC2"), 6);
+	// vmovups zmm0, zmmword ptr [rax+0x40]
+	assert_eq!(lde_int(b"\x62\xF1\x7C\x48\x10\x40\x01"), 7);
+	// vpshufd zmm0, zmm1, *
+	assert_eq!(lde_int(b"\x62\xF1\x7D\x48\x70\xC1*"), 7);
+	// vpternlogd zmm0, zmm1, zmm2, *
+	assert_eq!(lde_int(b"\x62\xF3\x75\x48\x25\xC2*"), 7);
+	// vpcmov xmm0, xmm1, xmm2, xmm3
+	assert_eq!(lde_int(b"\x8F\xE8\x70\xA2\xC2\x30"), 6);
+	// bextr eax, ecx, ****
+	assert_eq!(lde_int(b"\x8F\xEA\x78\x10\xC1****"), 9);
+	// pop qword ptr [rax], not XOP
+	assert_eq!(lde_int(b"\x8F\x00"), 2);
+	// reserved XOP map
+	assert_eq!(inst_len(b"\x8F\xEB\x78\x10\xC1"), Err(LenError::Invalid));
+	// aeskeygenassist xmm0, xmm1, *
+	assert_eq!(lde_int(b"\x66\x0F\x3A\xDF\xC1*"), 6);
+	// pclmulqdq xmm0, xmm1, *
+	assert_eq!(lde_int(b"\x66\x0F\x3A\x44\xC1*"), 6);
+	// sha1rnds4 xmm0, xmm1, *
+	assert_eq!(lde_int(b"\x0F\x3A\xCC\xC1*"), 5);
+	// adcx rax, rcx; adox rax, rcx; movdiri [rax], rax
+	assert_eq!(lde_int(b"\x66\x48\x0F\x38\xF6\xC1"), 6);
+	assert_eq!(lde_int(b"\xF3\x48\x0F\x38\xF6\xC1"), 6);
+	assert_eq!(lde_int(b"\x48\x0F\x38\xF9\x00"), 5);
+	// cmpeqps xmm0, xmm1; shufps xmm0, xmm1, 0
+	assert_eq!(lde_int(b"\x0F\xC2\xC1\x00"), 4);
+	assert_eq!(lde_int(b"\x0F\xC6\xC1\x00"), 4);
+	// pinsrw xmm0, ecx, *; cmpeqpd xmm0, xmmword ptr [rax+*]
+	assert_eq!(lde_int(b"\x66\x0F\xC4\xC1*"), 5);
+	assert_eq!(lde_int(b"\x66\x0F\xC2\x40*\x00"), 6);
+	// roundsd xmm0, xmm1, *; roundsd xmm0, qword ptr [r12+*], *; not to be confused with ud2
+	assert_eq!(lde_int(b"\x66\x0F\x3A\x0B\xC1*"), 6);
+	assert_eq!(lde_int(b"\x66\x41\x0F\x3A\x0B\x44\x24**"), 9);
+	assert_eq!(lde_int(b"\x0F\x0B"), 2);
+	// aesenc xmm0, xmm1
+	assert_eq!(lde_int(b"\x66\x0F\x38\xDC\xC1"), 5);
+	// sha256rnds2 xmm0, xmm1
+	assert_eq!(lde_int(b"\x0F\x38\xCB\xC1"), 4);
+	// pshufb xmm0, xmm1
+	assert_eq!(lde_int(b"\x66\x0F\x38\x00\xC1"), 5);
+	// phaddw xmm0, xmm1
+	assert_eq!(lde_int(b"\x66\x0F\x38\x01\xC1"), 5);
+	// pshufb mm0, mm1
+	assert_eq!(lde_int(b"\x0F\x38\x00\xC1"), 4);
+	// pmulhrsw xmm0, xmmword ptr [eax+*]
+	assert_eq!(lde_int(b"\x66\x0F\x38\x0B\x40*"), 6);
+}
+
+#[cfg(not(feature = "minimal"))]
+#[test]
+fn fpu() {
+	// fadd st, st(1); fmulp st(1), st; fld1; fnstsw ax
+	assert_eq!(lde_int(b"\xD8\xC1"), 2);
+	assert_eq!(lde_int(b"\xDE\xC9"), 2);
+	assert_eq!(lde_int(b"\xD9\xE8"), 2);
+	assert_eq!(lde_int(b"\xDF\xE0"), 2);
+	// fld QWORD PTR [rax+rax*4+****]
+	assert_eq!(lde_int(b"\xDD\x84\x00****"), 7);
+	// fld QWORD PTR [rip+****]
+	assert_eq!(lde_int(b"\xDD\x05****"), 6);
+	// fld DWORD PTR [rsp+*]
+	assert_eq!(lde_int(b"\xD9\x44\x24*"), 4);
+	// fld TBYTE PTR [rsp]
+	assert_eq!(lde_int(b"\xDB\x2C\x24"), 3);
+	// fld QWORD PTR [r8]
+	assert_eq!(lde_int(b"\x41\xDD\x00"), 3);
+}
+
+#[cfg(feature = "minimal")]
+#[test]
+fn minimal() {
+	// pfadd mm0, mm1
+	assert_eq!(lde_int(b"\x0F\x0F\xC1\x9E"), 0);
+	// vmovaps xmm0, xmm1
+	assert_eq!(lde_int(b"\xC5\xF8\x28\xC1"), 0);
+	// vaddps zmm0, zmm1, zmm2
+	assert_eq!(lde_int(b"\x62\xF1\x74\x48\x58\xC2"), 0);
+	// movaps xmm0, xmm1
+	assert_eq!(lde_int(b"\x0F\x28\xC1"), 0);
+	// pshufb xmm0, xmm1
+	assert_eq!(lde_int(b"\x66\x0F\x38\x00\xC1"), 0);
+	// fld st(1)
+	assert_eq!(lde_int(b"\xD9\xC1"), 0);
+	// movzx eax, cl
+	assert_eq!(lde_int(b"\x0F\xB6\xC1"), 3);
+}
+
+#[test]
+fn errors() {
+	assert_eq!(inst_len(b""), Err(LenError::Truncated { have: 0, need: 1 }));
+	assert_eq!(inst_len(b"\x0F"), Err(LenError::Truncated { have: 1, need: 2 }));
+	// mov esi, ****
+	assert_eq!(inst_len(b"\xBE**"), Err(LenError::Truncated { have: 3, need: 5 }));
+	// add DWORD PTR ds:****, eax
+	assert_eq!(inst_len(b"\x01\x05*"), Err(LenError::Truncated { have: 3, need: 6 }));
+	// invalid
+	assert_eq!(inst_len(b"\x0F\x04"), Err(LenError::Invalid));
+	// pusha, popa, into, push es, daa, aam *, jmp far, all valid outside of 64-bit mode
+	for &op in b"\x60\x61\xCE\x06\x27\xD4\xEA" {
+		assert_eq!(inst_len(&[op, 0, 0, 0, 0, 0, 0]), Err(LenError::Invalid));
+		assert!(x86::inst_len(&[op, 0, 0, 0, 0, 0, 0]).is_ok());
+	}
+	// more than 15 bytes
+	assert_eq!(lde_int(b"\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x90"), 15);
+	assert_eq!(inst_len(b"\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x90"), Err(LenError::Invalid));
+	assert_eq!(inst_len(b"\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x05****"), Err(LenError::Invalid));
+	// pop QWORD PTR [rax] and its reserved encodings
+	assert_eq!(lde_int(b"\x8F\x00"), 2);
+	assert_eq!(inst_len(b"\x8F\x20"), Err(LenError::Invalid));
+	assert_eq!(inst_len(b"\x8F"), Err(LenError::Truncated { have: 1, need: 2 }));
 }
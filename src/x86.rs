@@ -10,7 +10,11 @@ May contain errors...
 */
 
 use contains::Contains;
-use InstLen;
+use inst::vex_map;
+use {x64, InstLen, LenError};
+
+// Maximum length of an instruction in bytes.
+const MAX_LEN: u8 = 15;
 
 static TABLE_PREFIX: [u32; 8] = [
 	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
@@ -57,30 +61,43 @@ static TABLE_IMM_A: [u32; 8] = [
 	0b_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// C
 	0b_0_0_0_0_0_0_0_0_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// E
 ];
+// Opcodes which are always a single byte: not a prefix, without arguments and valid
+static TABLE_SINGLE_A: [u32; 8] = [
+	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
+	0b_0_0_0_0_0_0_1_1_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_1_0_0_0_0_0_0_1_1,// 0
+	0b_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1_0_0_0_0_0_0_0_1,// 2
+	0b_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// 4
+	0b_1_1_0_0_0_0_0_0_0_0_0_0_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 6
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1_1_1_0_0_1_1_1_1,// 8
+	0b_0_0_0_0_1_1_1_1_0_0_1_1_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// A
+	0b_0_0_0_1_0_0_0_0_0_1_0_1_1_0_1_1_0_0_0_0_0_0_1_1_0_0_0_0_0_0_0_0,// C
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_0_1_0_0_1_1_0_0_1_1_1_1_1_1_0_0,// E
+];
 //---- Two-byte opcodes ----
 static TABLE_MODRM_B: [u32; 8] = [
 	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
-	0b_1_1_1_1_0_0_0_0_0_0_0_0_0_1_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// 0
-	0b_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 2
+	0b_1_1_1_1_0_0_0_0_0_0_0_0_0_1_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// 0
+	0b_1_1_1_1_0_0_0_0_1_1_1_1_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 2
 	0b_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// 4
-	0b_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_0_0_0_1_1_1_0_1_1_1_1_1_1_1_1,// 6
+	0b_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_0_1_1_1_1_1_1_1_1,// 6
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// 8
-	0b_0_0_0_1_1_1_0_0_0_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_0_1_1_1_1_1_1,// A
+	0b_0_0_0_1_1_1_1_1_0_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// A
 	0b_1_1_1_1_1_1_1_1_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// C
 	0b_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1_1,// E
 ];
 static TABLE_INVALID_B: [u32; 8] = [
 	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
-	0b_0_0_0_0_1_0_0_0_0_0_1_0_1_0_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 0
-	0b_0_0_0_0_0_1_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_0_1_1_1_1_1_1_1_1,// 2
+	0b_0_0_0_0_1_0_0_0_0_0_1_0_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 0
+	0b_0_0_0_0_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_0_1_1_1_1_1_1_1_1,// 2
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 4
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1_1_0_0_0_0,// 6
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// 8
-	0b_0_0_0_0_0_0_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// A
-	0b_0_0_0_0_0_0_0_0_0_1_1_1_1_1_1_1_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// C
-	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_1,// E
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// A
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// C
+	0b_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0_0,// E
 ];
 //---- Three-byte opcodes 38 ----
+#[cfg(not(feature = "minimal"))]
 static TABLE_INVALID_C: [u32; 2] = [
 	/* 0 1 2 3 4 5 6 7 8 9 A B C D E F 0 1 2 3 4 5 6 7 8 9 A B C D E F */
 	0b_0_0_0_0_0_0_0_0_0_0_0_0_1_1_1_1_0_1_1_1_0_0_1_0_1_1_1_1_0_0_0_1,// 0
@@ -88,11 +105,41 @@ static TABLE_INVALID_C: [u32; 2] = [
 ];
 //---- Three-byte opcodes 3A ----
 
-pub fn inst_len(opcode: &[u8]) -> InstLen {
+pub fn inst_len(opcode: &[u8]) -> Result<InstLen, LenError> {
+	decode(opcode, 4, 4)
+}
+
+/// Length disassembles in 16-bit mode.
+pub fn inst_len16(opcode: &[u8]) -> Result<InstLen, LenError> {
+	decode(opcode, 2, 2)
+}
+
+// Length disassembles with the given default operand and address sizes in bytes, an address size of 8 selects 64-bit mode.
+// The default operand size is 2 or 4 bytes, 64-bit operands are selected by REX.W.
+// The operand and address size override prefixes toggle between 2 and 4 bytes.
+pub(crate) fn decode(opcode: &[u8], osize: u32, asize: u32) -> Result<InstLen, LenError> {
+	let long = asize == 8;
+	let (table_prefix, table_single_a, table_invalid_b) = if long {
+		(&x64::TABLE_PREFIX, &x64::TABLE_SINGLE_A, &x64::TABLE_INVALID_B)
+	}
+	else {
+		(&TABLE_PREFIX, &TABLE_SINGLE_A, &TABLE_INVALID_B)
+	};
+
+	// Fast path for the single byte opcodes
+	if let Some(&op) = opcode.first() {
+		if table_single_a.has(op) {
+			return Ok(InstLen { total_len: 1, op_len: 1, arg_len: 0, prefix_len: 0 });
+		}
+	}
+
 	let modrm;
 	let mut op: u8;
-	let (mut ddef, mut mdef) = (4u32, 4u32);
+	let (mut ddef, mut mdef) = (osize, asize);
 	let (mut dsize, mut msize) = (0u32, 0u32);
+	let mut rex_w = false;
+	// The ModRM byte always selects a register operand
+	let mut reg_form = false;
 	let mut it = opcode.iter();
 
 	// Prefixes
@@ -100,61 +147,172 @@ pub fn inst_len(opcode: &[u8]) -> InstLen {
 	loop {
 		op = match it.next() {
 			Some(&op) => op,
-			None => return InstLen::EMPTY,
+			None => return Err(truncated(opcode)),
 		};
-		if TABLE_PREFIX.has(op) {
+		// FWAIT is an instruction of its own unless it precedes an FPU instruction
+		if op == 0x9B && !matches!(it.as_slice().first(), Some(&(0xD8..=0xDF))) {
+			break;
+		}
+		if table_prefix.has(op) {
 			prefix_len += 1;
+			// Instructions are limited to 15 bytes
+			if prefix_len >= MAX_LEN {
+				return Err(LenError::Invalid);
+			}
 			// Operand-size override prefix
-			if op == 0x66 { ddef = 2u32; }
+			if op == 0x66 { ddef = if osize == 2 { 4 } else { 2 }; }
 			// Address-size override prefix
-			else if op == 0x67 { mdef = 2u32; }
+			else if op == 0x67 { mdef = if asize == 4 { 2 } else { 4 }; }
+			// REX prefixes with 0x8 set (W), only in effect directly preceding the opcode
+			rex_w = long && (0x48..0x50).has(op);
 		}
 		else {
 			break;
 		}
 	}
+	// REX.W takes precedence over the operand-size override prefix
+	if rex_w {
+		ddef = 4;
+	}
+
+	// VEX, EVEX and XOP prefixes select the opcode map in place of the escape bytes
+	let vex = match op {
+		// Outside of 64-bit mode they are LES, LDS and BOUND unless the next byte has the ModRM register form
+		0xC4 | 0xC5 | 0x62 => long || matches!(it.as_slice().first(), Some(&byte) if byte >= 0xC0),
+		// XOP is POP Ev unless the opcode map is 8 or above
+		0x8F => matches!(it.as_slice().first(), Some(&byte) if byte & 0x1F >= 8),
+		_ => false,
+	};
+	let vex_map = if vex {
+		// Not supported in minimal builds
+		#[cfg(feature = "minimal")]
+		return Err(LenError::Invalid);
+		#[cfg(not(feature = "minimal"))]
+		{
+			let byte = match it.next() {
+				Some(&byte) => byte,
+				None => return Err(truncated(opcode)),
+			};
+			let map = match op { 0xC5 => 1, 0xC4 | 0x8F => byte & 0x1F, _ => byte & 0x07 };
+			// Reject reserved opcode maps
+			if !((1..4).has(map) || op == 0x62 && (map == 5 || map == 6) || op == 0x8F && (8..11).has(map)) {
+				return Err(LenError::Invalid);
+			}
+			let skip = match op { 0xC5 => 0, 0xC4 | 0x8F => 1, _ => 2 };
+			for _ in 0..skip {
+				if it.next().is_none() {
+					return Err(truncated(opcode));
+				}
+			}
+			prefix_len += 2 + skip;
+			op = match it.next() {
+				Some(&op) => op,
+				None => return Err(truncated(opcode)),
+			};
+			map
+		}
+	}
+	else {
+		0
+	};
 
 	let mut op_len = 1;
-	if op == 0x0F {
+	// VEX, EVEX and XOP encoded opcodes (V)
+	if vex_map != 0 {
+		// Only vzeroupper and vzeroall lack a ModRM byte
+		modrm = !(vex_map == 1 && op == 0x77);
+		// Check for imm8
+		if vex_map == 3 || vex_map == 8 || vex_map == 1 && ((0x70..0x74).has(op) || op == 0xC2 || (0xC4..0xC7).has(op)) {
+			dsize += 1;
+		}
+		// Check for imm32, XOP map A
+		else if vex_map == 0xA {
+			dsize += 4;
+		}
+	}
+	else if op == 0x0F {
 		op = match it.next() {
 			Some(&op) => op,
-			None => return InstLen::EMPTY,
+			None => return Err(truncated(opcode)),
 		};
 		op_len += 1;
 		// Three-byte opcodes (C)
 		if op == 0x38 {
-			op = match it.next() {
-				Some(&op) => op,
-				None => return InstLen::EMPTY,
-			};
-			op_len += 1;
-			// Invalid opcodes
-			if if op < 0x40 { TABLE_INVALID_C.has(op) } else { !((0x40..0x42).has(op) || (0x80..0x82).has(op) || (0xF0..0xF2).has(op)) } { return InstLen::EMPTY; };
-			modrm = true;
+			// Not supported in minimal builds
+			#[cfg(feature = "minimal")]
+			return Err(LenError::Invalid);
+			#[cfg(not(feature = "minimal"))]
+			{
+				op = match it.next() {
+					Some(&op) => op,
+					None => return Err(truncated(opcode)),
+				};
+				op_len += 1;
+				// Invalid opcodes depend on the mandatory prefix
+				// SSSE3: 00..0B, 1C..1E, INVEPT, INVVPID and INVPCID: 80..82, SHA: C8..CD, GFNI: CF, AES: DB..DF, Key Locker: D8, DC..DF, FA..FB,
+				// MOVBE and CRC32: F0..F1, WRUSS: F5, WRSS, ADCX and ADOX: F6, MOVDIR64B and ENQCMD: F8, MOVDIRI: F9, RAO-INT: FC
+				let valid = match mandatory(&opcode[..prefix_len as usize]) {
+					0x00 => op < 0x0C || (0x1C..0x1F).has(op) || (0xC8..0xCE).has(op) || (0xF0..0xF2).has(op) || op == 0xF6 || op == 0xF9 || op == 0xFC,
+					0x66 => if op < 0x40 { !TABLE_INVALID_C.has(op) } else { (0x40..0x42).has(op) || (0x80..0x83).has(op) || op == 0xCF || (0xDB..0xE0).has(op) || (0xF0..0xF2).has(op) || (0xF5..0xF7).has(op) || op == 0xF8 || op == 0xFC },
+					0xF2 => (0xF0..0xF2).has(op) || op == 0xF8 || op == 0xFC,
+					0xF3 => op == 0xD8 || (0xDC..0xE0).has(op) || op == 0xF6 || op == 0xF8 || (0xFA..0xFD).has(op),
+					_ => false,
+				};
+				if !valid { return Err(LenError::Invalid); };
+				modrm = true;
+			}
 		}
 		// Three-byte opcodes (D)
 		else if op == 0x3A {
-			op = match it.next() {
-				Some(&op) => op,
-				None => return InstLen::EMPTY,
-			};
-			op_len += 1;
-			// Invalid opcodes
-			if !((0x08..0x10).has(op) || (0x14..0x18).has(op) || (0x20..0x23).has(op) || (0x40..0x43).has(op) || (0x60..0x64).has(op)) { return InstLen::EMPTY; };
-			modrm = true;
-			dsize += 1;
+			// Not supported in minimal builds
+			#[cfg(feature = "minimal")]
+			return Err(LenError::Invalid);
+			#[cfg(not(feature = "minimal"))]
+			{
+				op = match it.next() {
+					Some(&op) => op,
+					None => return Err(truncated(opcode)),
+				};
+				op_len += 1;
+				// Invalid opcodes depend on the mandatory prefix
+				// PALIGNR: 0F, PCLMULQDQ: 44, PCMPxSTRx: 60..63, SHA1RNDS4: CC, GFNI: CE..CF, AESKEYGENASSIST: DF, HRESET: F0
+				let valid = match mandatory(&opcode[..prefix_len as usize]) {
+					0x00 => op == 0x0F || op == 0xCC,
+					0x66 => (0x08..0x10).has(op) || (0x14..0x18).has(op) || (0x20..0x23).has(op) || (0x40..0x43).has(op) || op == 0x44 || (0x60..0x64).has(op) || (0xCE..0xD0).has(op) || op == 0xDF,
+					0xF3 => op == 0xF0,
+					_ => false,
+				};
+				if !valid { return Err(LenError::Invalid); };
+				modrm = true;
+				dsize += 1;
+			}
 		}
 		// Two-byte opcodes (B)
 		else {
 			// Invalid opcodes
-			if TABLE_INVALID_B.has(op) {
-				return InstLen::EMPTY;
+			if table_invalid_b.has(op) {
+				return Err(LenError::Invalid);
+			}
+			// MMX, 3DNow! and SSE opcodes are not supported in minimal builds
+			#[cfg(feature = "minimal")]
+			{
+				if op == 0x0E || op == 0x0F || (0x10..0x18).has(op) || (0x28..0x30).has(op) || (0x50..0x80).has(op) || (0xC2..0xC7).has(op) || (0xD0..0xFF).has(op) {
+					return Err(LenError::Invalid);
+				}
 			}
 			modrm = TABLE_MODRM_B.has(op);
-			// Check for imm8
-			if (0x70..0x74).has(op) || op == 0xA4 || op == 0xAC || op == 0xBA || op == 0xC2 || (0xC4..0xC7).has(op) {
+			reg_form = reg_form_b(op);
+			// Check for imm8, 3DNow! encodes the operation as an imm8 suffix
+			if op == 0x0F || (0x70..0x74).has(op) || op == 0xA4 || op == 0xAC || op == 0xBA || op == 0xC2 || (0xC4..0xC7).has(op) {
 				dsize += 1;
 			}
+			// Check for imm8 imm8, SSE4a: EXTRQ and INSERTQ
+			#[cfg(not(feature = "minimal"))]
+			{
+				if op == 0x78 && matches!(mandatory(&opcode[..prefix_len as usize]), 0x66 | 0xF2) {
+					dsize += 2;
+				}
+			}
 			// Check for imm16
 			if (op & 0xF0) == 0x80 {
 				dsize += ddef;
@@ -163,9 +321,24 @@ pub fn inst_len(opcode: &[u8]) -> InstLen {
 	}
 	// One-byte opcodes (A)
 	else {
+		// Reject invalid opcodes in 64-bit mode
+		if long && x64::TABLE_INVALID_A.has(op) {
+			return Err(LenError::Invalid);
+		}
+		// FPU opcodes are not supported in minimal builds
+		#[cfg(feature = "minimal")]
+		{
+			if (0xD8..0xE0).has(op) {
+				return Err(LenError::Invalid);
+			}
+		}
 		modrm = TABLE_MODRM_A.has(op);
-		// Check `test` opcode with immediate
-		if (op == 0xF6 || op == 0xF7) && (if let Some(&op) = it.clone().next() { op } else { return InstLen::EMPTY; } & 0x38) == 0 {
+		// POP Ev is only defined for ModRM reg field 0, the other encodings are reserved for XOP
+		if op == 0x8F && (if let Some(&op) = it.clone().next() { op } else { return Err(truncated(opcode)); } & 0x38) != 0 {
+			return Err(LenError::Invalid);
+		}
+		// Check `test` opcode with immediate, the ModRM reg field 1 is an alias of 0
+		if (op == 0xF6 || op == 0xF7) && (if let Some(&op) = it.clone().next() { op } else { return Err(truncated(opcode)); } & 0x30) == 0 {
 			dsize += if (op & 1) != 0 { ddef } else { 1 }
 		}
 		// Check for imm8
@@ -178,7 +351,13 @@ pub fn inst_len(opcode: &[u8]) -> InstLen {
 		}
 		// Check for immediate
 		if TABLE_IMM_A.has(op) {
-			dsize += ddef;
+			// `mov reg, imm` uses 64-bit immediate if REX.W is set
+			if (0xB8..0xC0).has(op) && rex_w {
+				dsize += 8;
+			}
+			else {
+				dsize += ddef;
+			}
 		}
 		// Special snowflake `movabs`
 		if (op & 0xFC) == 0xA0 {
@@ -190,21 +369,33 @@ pub fn inst_len(opcode: &[u8]) -> InstLen {
 	if modrm {
 		op = match it.next() {
 			Some(&op) => op,
-			None => return InstLen::EMPTY,
+			None => return Err(truncated(opcode)),
 		};
-		let mode = op & 0xC0;
+		let mode = if reg_form { 0xC0 } else { op & 0xC0 };
 		let rm = op & 0b111;
-		if mode != 0xC0 {
+		// 16-bit addressing has no Scaled Index Byte
+		if mdef == 2 {
+			if mode == 0x00 {
+				if rm == 0b110 {
+					msize += 2;
+				}
+			}
+			else if mode == 0x40 {
+				msize += 1;
+			}
+			else if mode == 0x80 {
+				msize += 2;
+			}
+		}
+		else if mode != 0xC0 {
 			if rm == 0b100 {
 				// Scaled Index Byte
 				op = match it.next() {
 					Some(&op) => op,
-					None => return InstLen::EMPTY,
+					None => return Err(truncated(opcode)),
 				};
-				if mode == 0x00 {
-					if (op & 0b111) == 0b101 {
-						msize += 4;
-					}
+				if mode == 0x00 && (op & 0b111) == 0b101 {
+					msize += 4;
 				}
 			}
 			if mode == 0x00 {
@@ -216,21 +407,76 @@ pub fn inst_len(opcode: &[u8]) -> InstLen {
 				msize += 1;
 			}
 			else if mode == 0x80 {
-				msize += mdef;
+				msize += 4;
 			}
 		}
 	}
 
 	// Get total length and bounds check
-	let total_len = ((it.as_slice().as_ptr() as usize).wrapping_sub(opcode.as_ptr() as usize)) as u32;
-	let total_len = total_len.wrapping_add(dsize + msize) as u8;
+	let total_len = (it.as_slice().as_ptr() as usize).wrapping_sub(opcode.as_ptr() as usize);
+	let total_len = total_len + (dsize + msize) as usize;
+	if total_len > MAX_LEN as usize {
+		return Err(LenError::Invalid);
+	}
+	if total_len > opcode.len() {
+		return Err(LenError::Truncated { have: opcode.len(), need: total_len });
+	}
 
+	let total_len = total_len as u8;
 	let arg_len = total_len - prefix_len - op_len;
-	if total_len as usize <= opcode.len() {
-		InstLen { total_len, op_len, arg_len, prefix_len }
+	Ok(InstLen { total_len, op_len, arg_len, prefix_len })
+}
+
+// Mandatory prefix selecting between the SSE variants of an opcode, 0 if there is none.
+// F2 and F3 take precedence over 66.
+#[cfg(not(feature = "minimal"))]
+fn mandatory(prefixes: &[u8]) -> u8 {
+	let mut mandatory = 0;
+	for &prefix in prefixes {
+		if prefix == 0xF2 || prefix == 0xF3 || prefix == 0x66 && mandatory == 0 {
+			mandatory = prefix;
+		}
 	}
-	else {
-		InstLen::EMPTY
+	mandatory
+}
+
+// The byte slice ran out before the instruction length could be determined.
+fn truncated(opcode: &[u8]) -> LenError {
+	LenError::Truncated { have: opcode.len(), need: opcode.len() + 1 }
+}
+
+// MOV to and from the control and debug registers ignore the ModRM mod field.
+fn reg_form_b(op: u8) -> bool {
+	(0x20..0x24).has(op)
+}
+
+/// Returns whether the ModRM byte of the length disassembled instruction always selects a register operand.
+///
+/// These have no SIB byte and no displacement whatever the ModRM mod field says.
+pub fn reg_form(bytes: &[u8], len: &InstLen) -> bool {
+	let start = len.prefix_len as usize;
+	if vex_map(&bytes[..start]) != 0 {
+		return false;
+	}
+	match bytes[start..start + len.op_len as usize] {
+		[0x0F, op] => reg_form_b(op),
+		_ => false,
+	}
+}
+
+/// Returns whether the length disassembled instruction has a ModRM byte following its opcode.
+pub fn has_modrm(bytes: &[u8], len: &InstLen) -> bool {
+	let start = len.prefix_len as usize;
+	// Only vzeroupper and vzeroall lack a ModRM byte
+	match vex_map(&bytes[..start]) {
+		0 => (),
+		1 => return bytes[start] != 0x77,
+		_ => return true,
+	}
+	match bytes[start..start + len.op_len as usize] {
+		[op] => TABLE_MODRM_A.has(op),
+		[0x0F, op] => TABLE_MODRM_B.has(op),
+		_ => true,
 	}
 }
 
@@ -238,19 +484,32 @@ pub fn inst_len(opcode: &[u8]) -> InstLen {
 
 #[cfg(test)]
 fn lde_int(bytes: &[u8]) -> u32 {
-	inst_len(bytes).total_len as u32
+	inst_len(bytes).map_or(0, |len| len.total_len as u32)
 }
 
 #[test]
 fn units() {
+	// test al, *
+	assert_eq!(lde_int(b"\xF6\xC0*"), 3);
+	// test al, * (reg field 1)
+	assert_eq!(lde_int(b"\xF6\xC8*"), 3);
+	// neg eax
+	assert_eq!(lde_int(b"\xF7\xD8"), 2);
+	// test DWORD PTR [eax], ****
+	assert_eq!(lde_int(b"\xF7\x00****"), 6);
+	// fwait
+	assert_eq!(lde_int(b"\x9B"), 1);
+	assert_eq!(lde_int(b"\x9B\x90"), 1);
 	// add al, *
 	assert_eq!(lde_int(b"\x04*"), 2);
+	// les eax, FWORD PTR [ecx]
+	assert_eq!(lde_int(b"\xC4\x01"), 2);
+	// bound eax, QWORD PTR [ecx+*]
+	assert_eq!(lde_int(b"\x62\x41*"), 3);
 	// mov DWORD PTR [ebp+*]
 	assert_eq!(lde_int(b"\x89\x5D*"), 3);
 	// test al, al
 	assert_eq!(lde_int(b"\x84\xC0"), 2);
-	// fld QWORD PTR [eax+eax*4+****]
-	assert_eq!(lde_int(b"\xDD\x84\x00****"), 7);
 	// mov esi, ****
 	assert_eq!(lde_int(b"\xBE****"), 5);
 	// mov eax, fs:****
@@ -261,6 +520,10 @@ fn units() {
 	assert_eq!(lde_int(b"\x67\xA1**"), 4);
 	// add BYTE PTR [bx+si+**], al
 	assert_eq!(lde_int(b"\x67\x00\x80**"), 5);
+	// addr16 mov eax, DWORD PTR [si]
+	assert_eq!(lde_int(b"\x67\x8B\x04"), 3);
+	// addr16 mov eax, DWORD PTR ds:**
+	assert_eq!(lde_int(b"\x67\x8B\x06**"), 5);
 	// inc eax
 	assert_eq!(lde_int(b"\x40"), 1);
 	// retn
@@ -269,6 +532,233 @@ fn units() {
 	assert_eq!(lde_int(b"\x0F\x1F\x40\x00"), 4);
 	// nop dword ptr [rax+****]
 	assert_eq!(lde_int(b"\x66\x0F\x0D\x80****"), 8);
+	// prefetch byte ptr [eax]
+	assert_eq!(lde_int(b"\x0F\x0D\x00"), 3);
+	// prefetchw byte ptr [eax]
+	assert_eq!(lde_int(b"\x0F\x0D\x08"), 3);
 	// clflush byte ptr [rax]
 	assert_eq!(lde_int(b"\x0F\xAE\x38"), 3);
+	// push ****
+	assert_eq!(lde_int(b"\x68****"), 5);
+	// push **
+	assert_eq!(lde_int(b"\x66\x68**"), 4);
+	// push *
+	assert_eq!(lde_int(b"\x6A*"), 2);
+	assert_eq!(lde_int(b"\x66\x6A*"), 3);
+	// endbr32, endbr64
+	assert_eq!(lde_int(b"\xF3\x0F\x1E\xFB"), 4);
+	assert_eq!(lde_int(b"\xF3\x0F\x1E\xFA"), 4);
+	// rdsspd eax
+	assert_eq!(lde_int(b"\xF3\x0F\x1E\xC8"), 4);
+	// nop (reserved) byte ptr [eax], byte ptr [eax+****]
+	assert_eq!(lde_int(b"\x0F\x1E\x00"), 3);
+	assert_eq!(lde_int(b"\x0F\x1E\x80****"), 7);
+	// syscall, sysret, sysenter, sysexit
+	assert_eq!(lde_int(b"\x0F\x05"), 2);
+	assert_eq!(lde_int(b"\x0F\x07"), 2);
+	assert_eq!(lde_int(b"\x0F\x34"), 2);
+	assert_eq!(lde_int(b"\x0F\x35"), 2);
+	// popcnt eax, ecx
+	assert_eq!(lde_int(b"\xF3\x0F\xB8\xC1"), 4);
+	// popcnt eax, dword ptr [ebp+*]
+	assert_eq!(lde_int(b"\xF3\x0F\xB8\x45*"), 5);
+	// tzcnt eax, ecx
+	assert_eq!(lde_int(b"\xF3\x0F\xBC\xC1"), 4);
+	// lzcnt eax, ecx
+	assert_eq!(lde_int(b"\xF3\x0F\xBD\xC1"), 4);
+	// bnd ret, bnd jmp ****, bnd call ****, bnd je ****
+	assert_eq!(lde_int(b"\xF2\xC3"), 2);
+	assert_eq!(lde_int(b"\xF2\xE9****"), 6);
+	assert_eq!(lde_int(b"\xF2\xE8****"), 6);
+	assert_eq!(lde_int(b"\xF2\x0F\x84****"), 7);
+	// mov eax, cr0; mov dr7, eax (the ModRM mod field is ignored)
+	assert_eq!(lde_int(b"\x0F\x20\xC0"), 3);
+	assert_eq!(lde_int(b"\x0F\x20\x00"), 3);
+	assert_eq!(lde_int(b"\x0F\x23\xB8"), 3);
+	// ud1 eax, dword ptr [eax+*]
+	assert_eq!(lde_int(b"\x0F\xB9\x40*"), 4);
+	// bswap eax, bswap edi
+	assert_eq!(lde_int(b"\x0F\xC8"), 2);
+	assert_eq!(lde_int(b"\x0F\xCF"), 2);
+	// ud0 eax, dword ptr [eax]
+	assert_eq!(lde_int(b"\x0F\xFF\x00"), 3);
+	// xstore, rep xsha1
+	assert_eq!(lde_int(b"\x0F\xA7\xC0"), 3);
+	assert_eq!(lde_int(b"\xF3\x0F\xA6\xC8"), 4);
+}
+
+#[cfg(not(feature = "minimal"))]
+#[test]
+fn simd() {
+	// fclex
+	assert_eq!(lde_int(b"\x9B\xDB\xE2"), 3);
+	// femms
+	assert_eq!(lde_int(b"\x0F\x0E"), 2);
+	// adcx eax, ecx; adox eax, ecx; wrssd [eax], eax; wrussd [eax], eax
+	assert_eq!(lde_int(b"\x66\x0F\x38\xF6\xC1"), 5);
+	assert_eq!(lde_int(b"\xF3\x0F\x38\xF6\xC1"), 5);
+	assert_eq!(lde_int(b"\x0F\x38\xF6\x00"), 4);
+	assert_eq!(lde_int(b"\x66\x0F\x38\xF5\x00"), 5);
+	// invpcid eax, [eax]; movdir64b eax, [eax]; enqcmd eax, [eax]; movdiri [eax], eax
+	assert_eq!(lde_int(b"\x66\x0F\x38\x82\x00"), 5);
+	assert_eq!(lde_int(b"\x66\x0F\x38\xF8\x00"), 5);
+	assert_eq!(lde_int(b"\xF2\x0F\x38\xF8\x00"), 5);
+	assert_eq!(lde_int(b"\x0F\x38\xF9\x00"), 4);
+	// aadd, aand, aor, axor [eax], eax
+	assert_eq!(lde_int(b"\x0F\x38\xFC\x00"), 4);
+	assert_eq!(lde_int(b"\x66\x0F\x38\xFC\x00"), 5);
+	assert_eq!(lde_int(b"\xF2\x0F\x38\xFC\x00"), 5);
+	assert_eq!(lde_int(b"\xF3\x0F\x38\xFC\x00"), 5);
+	// aesenc128kl xmm0, [eax+*]; aesencwide128kl [eax]; encodekey128 eax, ecx
+	assert_eq!(lde_int(b"\xF3\x0F\x38\xDC\x40*"), 6);
+	assert_eq!(lde_int(b"\xF3\x0F\x38\xD8\x00"), 5);
+	assert_eq!(lde_int(b"\xF3\x0F\x38\xFA\xC1"), 5);
+	// hreset *
+	assert_eq!(lde_int(b"\xF3\x0F\x3A\xF0\xC0*"), 6);
+	// adcx without its mandatory prefix is undefined
+	assert_eq!(lde_int(b"\xF2\x0F\x38\xF6\xC1"), 0);
+	// psrlw mm1, *; psrldq xmm5, *
+	assert_eq!(lde_int(b"\x0F\x71\xD1*"), 4);
+	assert_eq!(lde_int(b"\x66\x0F\x73\xDD*"), 5);
+	// extrq xmm0, *, *; insertq xmm2, xmm0, *, *; vmread eax, eax
+	assert_eq!(lde_int(b"\x66\x0F\x78\xC0**"), 6);
+	assert_eq!(lde_int(b"\xF2\x0F\x78\xD0**"), 6);
+	assert_eq!(lde_int(b"\x0F\x78\xC0"), 3);
+	// pfadd mm0, mm1
+	assert_eq!(lde_int(b"\x0F\x0F\xC1\x9E"), 4);
+	// pfmul mm0, QWORD PTR [eax+*]
+	assert_eq!(lde_int(b"\x0F\x0F\x40*\xB4"), 5);
+	// vpxord zmm0, zmm0, zmm0
+	assert_eq!(lde_int(b"\x62\xF1\x7D\x48\xEF\xC0"), 6);
+	// vmovups zmm0, zmmword ptr [eax+0x40]
+	assert_eq!(lde_int(b"\x62\xF1\x7C\x48\x10\x40\x01"), 7);
+	// vmovaps xmm0, xmm1
+	assert_eq!(lde_int(b"\xC5\xF8\x28\xC1"), 4);
+	// vzeroall
+	assert_eq!(lde_int(b"\xC5\xFC\x77"), 3);
+	// vpshufb xmm0, xmm1, xmmword ptr [esp+****]
+	assert_eq!(lde_int(b"\xC4\xE2\x71\x00\x84\x24****"), 10);
+	// vpermq ymm0, ymm1, *
+	assert_eq!(lde_int(b"\xC4\xE3\xFD\x00\xC1*"), 6);
+	// fld QWORD PTR [eax+eax*4+****]
+	assert_eq!(lde_int(b"\xDD\x84\x00****"), 7);
+	// fadd st, st(1)
+	assert_eq!(lde_int(b"\xD8\xC1"), 2);
+	// aeskeygenassist xmm0, xmm1, *
+	assert_eq!(lde_int(b"\x66\x0F\x3A\xDF\xC1*"), 6);
+	// pclmulqdq xmm0, xmm1, *
+	assert_eq!(lde_int(b"\x66\x0F\x3A\x44\xC1*"), 6);
+	// sha1rnds4 xmm0, xmm1, *
+	assert_eq!(lde_int(b"\x0F\x3A\xCC\xC1*"), 5);
+	// aesenc xmm0, xmm1
+	assert_eq!(lde_int(b"\x66\x0F\x38\xDC\xC1"), 5);
+	// sha256rnds2 xmm0, xmm1
+	assert_eq!(lde_int(b"\x0F\x38\xCB\xC1"), 4);
+	// pshufb xmm0, xmm1
+	assert_eq!(lde_int(b"\x66\x0F\x38\x00\xC1"), 5);
+	// phaddw xmm0, xmm1
+	assert_eq!(lde_int(b"\x66\x0F\x38\x01\xC1"), 5);
+	// pshufb mm0, mm1
+	assert_eq!(lde_int(b"\x0F\x38\x00\xC1"), 4);
+	// pmulhrsw xmm0, xmmword ptr [eax+*]
+	assert_eq!(lde_int(b"\x66\x0F\x38\x0B\x40*"), 6);
+	// vpcmov xmm0, xmm1, xmm2, xmm3
+	assert_eq!(lde_int(b"\x8F\xE8\x70\xA2\xC2\x30"), 6);
+	// vprotb xmm0, xmm1, xmm0
+	assert_eq!(lde_int(b"\x8F\xE9\x78\x90\xC1"), 5);
+	// pcmpistri xmm0, xmm1, *
+	assert_eq!(lde_int(b"\x66\x0F\x3A\x63\xC1*"), 6);
+	// crc32 eax, ecx and crc32 eax, cx
+	assert_eq!(lde_int(b"\xF2\x0F\x38\xF1\xC1"), 5);
+	assert_eq!(lde_int(b"\x66\xF2\x0F\x38\xF1\xC1"), 6);
+	// movbe ecx, DWORD PTR [eax]
+	assert_eq!(lde_int(b"\x0F\x38\xF0\x08"), 4);
+	// palignr mm0, mm1, *
+	assert_eq!(lde_int(b"\x0F\x3A\x0F\xC1*"), 5);
+	// Invalid with or without the mandatory prefix
+	assert_eq!(inst_len(b"\x0F\x3A\x63\xC1*"), Err(LenError::Invalid));
+	assert_eq!(inst_len(b"\x66\x0F\x3A\xCC\xC1*"), Err(LenError::Invalid));
+	assert_eq!(inst_len(b"\xF3\x0F\x38\xF0\x08"), Err(LenError::Invalid));
+	assert_eq!(inst_len(b"\xF2\x0F\x38\x00\xC1"), Err(LenError::Invalid));
+	assert_eq!(inst_len(b"\x0F\x38\x10\xC1"), Err(LenError::Invalid));
+}
+
+#[cfg(feature = "minimal")]
+#[test]
+fn minimal() {
+	// pfadd mm0, mm1
+	assert_eq!(lde_int(b"\x0F\x0F\xC1\x9E"), 0);
+	// vmovaps xmm0, xmm1
+	assert_eq!(lde_int(b"\xC5\xF8\x28\xC1"), 0);
+	// movaps xmm0, xmm1
+	assert_eq!(lde_int(b"\x0F\x28\xC1"), 0);
+	// pshufb xmm0, xmm1
+	assert_eq!(lde_int(b"\x66\x0F\x38\x00\xC1"), 0);
+	// fld st(1)
+	assert_eq!(lde_int(b"\xD9\xC1"), 0);
+	// movzx eax, cl
+	assert_eq!(lde_int(b"\x0F\xB6\xC1"), 3);
+}
+
+#[test]
+fn real_mode() {
+	let lde_int = |bytes: &[u8]| inst_len16(bytes).map_or(0, |len| len.total_len as u32);
+	// mov ax, **
+	assert_eq!(lde_int(b"\xB8**"), 3);
+	// mov eax, ****
+	assert_eq!(lde_int(b"\x66\xB8****"), 6);
+	// mov ax, WORD PTR [bx+si+*]
+	assert_eq!(lde_int(b"\x8B\x40*"), 3);
+	// mov ax, WORD PTR ds:**
+	assert_eq!(lde_int(b"\x8B\x06**"), 4);
+	// mov ax, WORD PTR [bp+**]
+	assert_eq!(lde_int(b"\x8B\x86**"), 4);
+	// mov ax, WORD PTR [eax+ecx*4]
+	assert_eq!(lde_int(b"\x67\x8B\x04\x88"), 4);
+	// call **
+	assert_eq!(lde_int(b"\xE8**"), 3);
+	// jmp **:**
+	assert_eq!(lde_int(b"\xEA****"), 5);
+	// mov al, BYTE PTR ds:**
+	assert_eq!(lde_int(b"\xA0**"), 3);
+	// les ax, DWORD PTR [bx]
+	assert_eq!(lde_int(b"\xC4\x07"), 2);
+}
+
+#[test]
+fn single() {
+	// The fast path must agree with the tables
+	for op in 0..=255 {
+		if TABLE_SINGLE_A.has(op) {
+			assert!(!TABLE_PREFIX.has(op) && op != 0x0F && op != 0x9B, "{:02x}", op);
+			assert!(!TABLE_MODRM_A.has(op) && !TABLE_IMM8_A.has(op) && !TABLE_IMM_A.has(op), "{:02x}", op);
+		}
+		if x64::TABLE_SINGLE_A.has(op) {
+			assert!(!x64::TABLE_PREFIX.has(op) && op != 0x0F && op != 0x9B && !x64::TABLE_INVALID_A.has(op), "{:02x}", op);
+			assert!(!TABLE_MODRM_A.has(op) && !TABLE_IMM8_A.has(op) && !TABLE_IMM_A.has(op), "{:02x}", op);
+		}
+	}
+}
+
+#[test]
+fn errors() {
+	assert_eq!(inst_len(b""), Err(LenError::Truncated { have: 0, need: 1 }));
+	assert_eq!(inst_len(b"\x0F"), Err(LenError::Truncated { have: 1, need: 2 }));
+	// mov esi, ****
+	assert_eq!(inst_len(b"\xBE**"), Err(LenError::Truncated { have: 3, need: 5 }));
+	// add DWORD PTR ds:****, eax
+	assert_eq!(inst_len(b"\x01\x05*"), Err(LenError::Truncated { have: 3, need: 6 }));
+	// invalid
+	assert_eq!(inst_len(b"\x0F\x04"), Err(LenError::Invalid));
+	// mov to and from the test registers, removed after the 486
+	assert_eq!(inst_len(b"\x0F\x24\xC0"), Err(LenError::Invalid));
+	assert_eq!(inst_len(b"\x0F\x26\xC0"), Err(LenError::Invalid));
+	// more than 15 bytes
+	assert_eq!(lde_int(b"\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x90"), 15);
+	assert_eq!(inst_len(b"\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x90"), Err(LenError::Invalid));
+	assert_eq!(inst_len(b"\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x66\x05****"), Err(LenError::Invalid));
+	// pop DWORD PTR [eax] and its reserved encodings
+	assert_eq!(lde_int(b"\x8F\x00"), 2);
+	assert_eq!(inst_len(b"\x8F\x20"), Err(LenError::Invalid));
+	assert_eq!(inst_len(b"\x8F"), Err(LenError::Truncated { have: 1, need: 2 }));
 }
@@ -0,0 +1,213 @@
+//! Compares the instruction lengths against the iced-x86 decoder.
+//!
+//! Random byte sequences are decoded in 16, 32 and 64-bit mode, both the lengths and the validity are compared.
+//! The intentional differences are listed in `allowed`.
+//! Mismatches are reported with the bytes which do not contribute to the mismatch removed.
+
+#![cfg(not(feature = "minimal"))]
+
+extern crate iced_x86;
+extern crate lde;
+
+use iced_x86::{Decoder, DecoderOptions, Instruction, Mnemonic, OpKind};
+use lde::{Isa, OpcodeMap, X16, X86, X64};
+
+// Decodes the first instruction with iced-x86.
+fn decode(bitness: u32, bytes: &[u8], options: u32) -> Instruction {
+	Decoder::new(bitness, bytes, options).decode()
+}
+
+// Length of the first instruction according to iced-x86, 0 if invalid or truncated.
+fn reference(bitness: u32, bytes: &[u8], options: u32) -> u32 {
+	let inst = decode(bitness, bytes, options);
+	if inst.is_invalid() { 0 } else { inst.len() as u32 }
+}
+
+// Intentional differences from iced-x86, every other difference in length or validity is reported.
+fn allowed<X: Isa>(bitness: u32, bytes: &[u8], len: u32, expected: u32) -> bool {
+	// FWAIT is decoded as part of the FPU instruction which follows it
+	let inst = decode(bitness, bytes, DecoderOptions::NO_INVALID_CHECK);
+	if inst.mnemonic() == Mnemonic::Wait && matches!(bytes.get(inst.len()), Some(&(0xD8..=0xDF))) {
+		let next = &bytes[inst.len()..];
+		return len == inst.len() as u32 + X::ld(next) && mismatch::<X>(bitness, next).is_none();
+	}
+	// The near branches honor the operand-size override prefix in 64-bit mode as on AMD processors
+	if bitness == 64 {
+		let inst = decode(bitness, bytes, DecoderOptions::AMD | DecoderOptions::NO_INVALID_CHECK);
+		if inst.op0_kind() == OpKind::NearBranch16 {
+			return inst.len() as u32 == len;
+		}
+	}
+
+	// Invalid opcodes are only rejected on a best-effort basis, the remaining exceptions are invalid encodings which are accepted
+	if len == 0 || expected != 0 {
+		return false;
+	}
+	// LOCK prefixes and operand restrictions are not validated
+	if reference(bitness, bytes, DecoderOptions::NO_INVALID_CHECK) == len {
+		return true;
+	}
+	let inst = match X::iter(bytes, X::as_va(0)).next() {
+		Some(inst) => inst,
+		None => return false,
+	};
+	// The fields of the VEX, EVEX and XOP prefixes and the opcodes in their maps are not validated
+	if let OpcodeMap::Vex | OpcodeMap::Evex | OpcodeMap::Xop = inst.opcode_map() {
+		return true;
+	}
+	// The 3DNow! operation in the imm8 suffix
+	if inst.op_bytes() == b"\x0F\x0F" {
+		let mut sibling = bytes.to_vec();
+		sibling[inst.len() - 1] = 0x9E;
+		return reference(bitness, &sibling, DecoderOptions::NO_INVALID_CHECK) == len;
+	}
+	// Otherwise the length must match a valid encoding which differs only in the fields which are not validated
+	let (prefixes, op) = (inst.prefix_bytes(), inst.op_bytes());
+	// The mandatory prefixes, added or replaced by another mandatory prefix or a segment prefix without effect
+	let mut prefix_forms = vec![prefixes.to_vec()];
+	let is_mandatory = |prefix: u8| prefix == 0x66 || prefix == 0xF2 || prefix == 0xF3;
+	for &mandatory in &[0x66, 0xF2, 0xF3, 0x3E] {
+		let mut form: Vec<u8> = prefixes.iter().map(|&prefix| if is_mandatory(prefix) { mandatory } else { prefix }).collect();
+		if !prefixes.iter().any(|&prefix| is_mandatory(prefix)) {
+			form.insert(0, mandatory);
+		}
+		prefix_forms.push(form);
+	}
+	// The ModRM reg field of opcode groups, the register form of memory only operands and vice versa
+	let at = prefixes.len() + op.len();
+	let mut modrm_forms = vec![bytes[at..].to_vec()];
+	if let Some(modrm) = inst.modrm() {
+		let end = at + 1 + inst.sib().map_or(0, |_| 1) + inst.disp_size().unwrap_or(0) as usize;
+		for reg in 0..8 {
+			modrm_forms.push([&[modrm & 0xC7 | reg << 3], &bytes[at + 1..]].concat());
+			modrm_forms.push([&[0xC0 | reg << 3], &bytes[end..]].concat());
+			modrm_forms.push([&[reg << 3], &bytes[end..]].concat());
+		}
+	}
+	// The bytes following the instruction are kept in every form
+	let tail = (bytes.len() - len as usize) as u32;
+	prefix_forms.iter().any(|prefixes| modrm_forms.iter().any(|rest| {
+		let sibling = [&prefixes[..], op, rest].concat();
+		reference(bitness, &sibling, DecoderOptions::NO_INVALID_CHECK) == sibling.len() as u32 - tail
+	}))
+}
+
+// Returns the lengths if the decoders disagree, a length of 0 means the bytes are rejected.
+fn mismatch<X: Isa>(bitness: u32, bytes: &[u8]) -> Option<(u32, u32)> {
+	let (len, expected) = (X::ld(bytes), reference(bitness, bytes, DecoderOptions::NONE));
+	if len == expected || allowed::<X>(bitness, bytes, len, expected) { None } else { Some((len, expected)) }
+}
+
+// Shrinks a mismatching byte sequence by dropping bytes which do not contribute to the mismatch.
+fn minimize<X: Isa>(bitness: u32, bytes: &[u8]) -> Vec<u8> {
+	let mut bytes = bytes.to_vec();
+	let mut i = 0;
+	while i < bytes.len() {
+		let mut shrunk = bytes.clone();
+		shrunk.remove(i);
+		if mismatch::<X>(bitness, &shrunk).is_some() {
+			bytes = shrunk;
+		}
+		else {
+			i += 1;
+		}
+	}
+	bytes
+}
+
+fn check<X: Isa>(bitness: u32, bytes: &[u8], failures: &mut Vec<String>) {
+	if mismatch::<X>(bitness, bytes).is_some() {
+		let bytes = minimize::<X>(bitness, bytes);
+		let (len, expected) = mismatch::<X>(bitness, &bytes).unwrap();
+		failures.push(format!("{}-bit {:02X?}: {} != {}", bitness, bytes, len, expected));
+	}
+}
+
+// Xorshift, deterministic across runs.
+struct Rng(u64);
+impl Rng {
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+	fn pick<'a>(&mut self, items: &[&'a [u8]]) -> &'a [u8] {
+		items[self.next() as usize % items.len()]
+	}
+}
+
+// Instructions from the unit tests, the trailing bytes are filled in randomly.
+static SEEDS: &[&[u8]] = &[
+	b"\xF6\xC0", b"\xF7\x00", b"\x9B\xDB\xE2", b"\x48\x83\xEC", b"\x48\x8D\x0D", b"\x40\x55", b"\x48\xA3", b"\x67\x48\xA3",
+	b"\x48\xC7\x85", b"\x66\xC7\x44\x24", b"\x0F\x1F\x40", b"\x66\x0F\x0D\x80", b"\x0F\x0D\x08", b"\x66\x66\x0F\x1F\x84",
+	b"\xF3\xA4", b"\x49\xBF", b"\x68", b"\x66\x68", b"\x6A", b"\xF3\x0F\x1E\xFA", b"\xF3\x48\x0F\x1E\xC8", b"\x0F\x1E\x80",
+	b"\x0F\x05", b"\xF3\x0F\xB8\x45", b"\xF3\x48\x0F\xB8\xC1", b"\xC1\xE0", b"\xC2", b"\xCC", b"\xFF\x10", b"\xF2\xC3",
+	b"\xF2\xE9", b"\x67\x8B\x04\x25", b"\x0F\x18\x80", b"\x48\xB8", b"\x66\x48\x05", b"\x48\xA1", b"\x67\xA1", b"\x8F\x00",
+	b"\x0F\x0F\x40", b"\x62\xF1\xFD\x48\xEF\x40", b"\xC5\xFE\x6F\x40", b"\xC4\xE3\xFD\x00\xC1", b"\x62\xF3\x75\x48\x25",
+	b"\x66\x0F\x3A\xDF\xC1", b"\x0F\x3A\xCC\xC1", b"\x66\x0F\x38\x0B\x40", b"\x66\x0F\x3A\x63\xC1", b"\xF2\x0F\x38\xF1\xC1",
+	b"\x8F\xE8\x70\xA2\xC2", b"\x8F\xEA\x78\x10\xC1", b"\xDD\x84\x00", b"\x8B\x04\x24",
+];
+
+static PREFIXES: &[&[u8]] = &[b"", b"", b"\x66", b"\x67", b"\xF2", b"\xF3", b"\xF0", b"\x2E", b"\x64", b"\x66\x67"];
+static ESCAPES: &[&[u8]] = &[b"", b"", b"", b"\x0F", b"\x0F", b"\x0F\x38", b"\x0F\x3A", b"\xC4", b"\xC5", b"\x62", b"\x8F"];
+
+fn run<X: Isa>(bitness: u32, rex: bool) -> Vec<String> {
+	let mut rng = Rng(0x2545F4914F6CDD1D ^ bitness as u64);
+	let mut failures = Vec::new();
+	let mut bytes = Vec::with_capacity(32);
+	for &seed in SEEDS {
+		for _ in 0..64 {
+			bytes.clear();
+			bytes.extend_from_slice(seed);
+			bytes.extend((0..15).map(|_| rng.next() as u8));
+			check::<X>(bitness, &bytes, &mut failures);
+		}
+	}
+	// Every opcode in every opcode map with every mandatory prefix
+	for &prefix in &[&b""[..], b"\x66", b"\xF2", b"\xF3"] {
+		for &escape in &[&b""[..], b"\x0F", b"\x0F\x38", b"\x0F\x3A"] {
+			for op in 0..=255 {
+				for _ in 0..8 {
+					bytes.clear();
+					bytes.extend_from_slice(prefix);
+					bytes.extend_from_slice(escape);
+					bytes.push(op);
+					bytes.extend((0..15).map(|_| rng.next() as u8));
+					check::<X>(bitness, &bytes, &mut failures);
+				}
+			}
+		}
+	}
+	for _ in 0..100_000 {
+		bytes.clear();
+		bytes.extend_from_slice(rng.pick(PREFIXES));
+		if rex && rng.next() & 1 != 0 {
+			bytes.push(0x40 | rng.next() as u8 & 0x0F);
+		}
+		bytes.extend_from_slice(rng.pick(ESCAPES));
+		bytes.extend((0..15).map(|_| rng.next() as u8));
+		check::<X>(bitness, &bytes, &mut failures);
+	}
+	failures.sort();
+	failures.dedup();
+	failures
+}
+
+#[test]
+fn x16() {
+	let failures = run::<X16>(16, false);
+	assert!(failures.is_empty(), "{} mismatches:\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn x86() {
+	let failures = run::<X86>(32, false);
+	assert!(failures.is_empty(), "{} mismatches:\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn x64() {
+	let failures = run::<X64>(64, true);
+	assert!(failures.is_empty(), "{} mismatches:\n{}", failures.len(), failures.join("\n"));
+}
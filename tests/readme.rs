@@ -0,0 +1,47 @@
+//! Pins the examples from the readme.
+
+extern crate lde;
+
+use lde::{Isa, X86, X64};
+
+#[test]
+fn ld() {
+	let result = X64::ld(b"\x40\x55\x48\x83\xEC\xFC\x00\x80");
+	assert_eq!(result, 2);
+}
+
+#[test]
+fn iter() {
+	let code = b"\x40\x55\x48\x83\xEC*\x00\x80";
+
+	let insts: Vec<_> = X64::iter(code, 0x1000).map(|inst| (inst.va(), inst.bytes())).collect();
+	assert_eq!(insts, [(0x1000, &code[0..2]), (0x1002, &code[2..6])]);
+
+	let lines: Vec<_> = X64::iter(code, 0x1000).map(|inst| format!("{:x}: {:x}", inst.va(), inst)).collect();
+	assert_eq!(lines, ["1000: 4055", "1002: 4883ec2a"]);
+}
+
+#[test]
+fn hook() {
+	const INPUT_CODE: &[u8] = b"\x56\x33\xF6\x57\xBF\xA0\x10\x40\x00\x85\xD2\x74\x10\x8B\xF2\x8B\xFA";
+
+	let mut count = 0;
+	for inst in X86::iter(INPUT_CODE, 0x1000) {
+		count += inst.bytes().len();
+		if count >= 5 {
+			break;
+		}
+	}
+
+	assert_eq!(count, 9);
+}
+
+#[test]
+fn formatting() {
+	let iter = X64::iter(b"\x40\x55\x48\x83\xEC*\x00\x80", 0);
+
+	assert_eq!(format!("{:?}", iter), "[4055] [4883ec2a] 0080");
+	assert_eq!(format!("{:#?}", iter), "[40 55] [48 83 ec 2a] 00 80");
+	assert_eq!(format!("{:}", iter), "4055\n4883ec2a\n");
+	assert_eq!(format!("{:#}", iter), "40 55\n48 83 ec 2a\n");
+}